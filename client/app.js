@@ -5,19 +5,107 @@ let context = canvas.getContext("2d");
 // store animation frame
 let raf;
 
-function loop(){
+// camera frames go to quick-compare as JPEG unless ?format=rgba
+const RGBA = new URLSearchParams(location.search).get("format") === "rgba";
+const HEADER_SIZE = 17;
+let socket = new WebSocket(`ws://${location.host}/frames`);
+socket.binaryType = "arraybuffer";
+let sending = false;
+
+// the first message tells whether to send the camera or show the comparison
+socket.onmessage = (message) => {
+  let role = JSON.parse(message.data).role;
+  document.querySelector("h1").textContent = role === "camera" ? "Camera" : "Viewer";
+  if (role === "camera") {
+    canvas.hidden = false;
+    startCamera();
+  } else {
+    startViewer();
+  }
+};
+
+// e.g. when another camera is connected already
+socket.onclose = (event) => {
+  if (event.reason) {
+    document.querySelector("h1").textContent = event.reason;
+  }
+};
+
+// kind (0 JPEG, 1 RGBA), timestamp in ms as f64, width and height as u32,
+// all little endian, followed by the image
+function header(kind, timestamp) {
+  let header = new DataView(new ArrayBuffer(HEADER_SIZE));
+  header.setUint8(0, kind);
+  header.setFloat64(1, timestamp, true);
+  header.setUint32(9, canvas.width, true);
+  header.setUint32(13, canvas.height, true);
+  return header.buffer;
+}
+
+function send(timestamp) {
+  // skip frames while the last one is still on its way
+  if (socket.readyState !== WebSocket.OPEN || sending || socket.bufferedAmount > 0) {
+    return;
+  }
+  if (RGBA) {
+    let pixels = context.getImageData(0, 0, canvas.width, canvas.height).data;
+    socket.send(new Blob([header(1, timestamp), pixels]));
+    return;
+  }
+  sending = true;
+  canvas.toBlob((jpeg) => {
+    sending = false;
+    if (jpeg && socket.readyState === WebSocket.OPEN) {
+      socket.send(new Blob([header(0, timestamp), jpeg]));
+    }
+  }, "image/jpeg", 0.9);
+}
+
+function loop(timestamp){
  context.drawImage(video, 0, 0, canvas.width, canvas.height);
+ send(timestamp);
  raf = requestAnimationFrame(loop);
 }
 
-navigator.mediaDevices.getUserMedia({video: true, audio: false}).then((localStream) => {
-  video.srcObject = localStream;
-  video.onplaying = () => {
-    raf = requestAnimationFrame(loop);
+function startCamera() {
+  navigator.mediaDevices.getUserMedia({video: true, audio: false}).then((localStream) => {
+    video.srcObject = localStream;
+    video.onplaying = () => {
+      canvas.width = video.videoWidth;
+      canvas.height = video.videoHeight;
+      raf = requestAnimationFrame(loop);
+    };
+    video.play();
+    localStream.getTracks().forEach(track => {
+      console.log(track, localStream);
+    });
+  }).catch(e => alert(e));
+}
+
+// show every JPEG of the comparison and send the controls back
+function startViewer() {
+  let view = document.getElementById("view");
+  let controls = document.getElementById("controls");
+  view.hidden = false;
+  controls.hidden = false;
+  socket.onmessage = (message) => {
+    let previous = view.src;
+    view.src = URL.createObjectURL(new Blob([message.data], {type: "image/jpeg"}));
+    if (previous) {
+      URL.revokeObjectURL(previous);
+    }
   };
-  video.play();
-  localStream.getTracks().forEach(track => {
-    console.log(track, localStream);
+  controls.querySelectorAll("[data-control]").forEach(button => {
+    button.onclick = () => socket.send(button.dataset.control);
   });
-}).catch(e => alert(e));
-
+  let seek = document.getElementById("seek");
+  document.getElementById("seek-button").onclick = () => socket.send(`seek ${seek.value}`);
+  document.onkeydown = (event) => {
+    let control = {" ": "toggle", "ArrowRight": "next", "ArrowLeft": "previous",
+      "n": "next-difference", "p": "previous-difference", "r": "reference"}[event.key];
+    if (control) {
+      event.preventDefault();
+      socket.send(control);
+    }
+  };
+}
@@ -0,0 +1,138 @@
+use std::path::Path;
+
+use crate::renderer::Renderer;
+
+/// Delay between the two inputs of a flicker in milliseconds.
+const FLICKER_DELAY: u32 = 500;
+/// Delay between frames in milliseconds of videos without a frame rate, 25
+/// frames per second as ffmpeg assumes.
+const DEFAULT_FRAME_DELAY: u32 = 40;
+
+/// Export `frames` frames of the comparison starting at `start` as an
+/// animated GIF or APNG, depending on the extension of `output`. With
+/// `flicker` the loop instead alternates between those two videos, each shown
+/// on its own at `start`. Fails for GIFs larger than 65535 pixels either way.
+pub fn run(
+    renderer: &mut Renderer,
+    output: &str,
+    start: usize,
+    frames: usize,
+    flicker: Option<(usize, usize)>,
+    size: (u32, u32),
+    delay: Option<u32>,
+) -> Result<(), String> {
+    let path = Path::new(output);
+    let gif = matches!(path.extension(), Some(extension) if extension.eq_ignore_ascii_case("gif"));
+    if gif {
+        // Before rendering anything.
+        gif_size(size)?;
+    }
+
+    renderer.seek(start);
+    renderer.update();
+
+    let (images, delay) = match flicker {
+        Some((a, b)) => {
+            let images = [a, b]
+                .iter()
+                .map(|&index| {
+                    renderer.set_solo(Some(index));
+                    renderer.capture(size)
+                })
+                .collect::<Vec<_>>();
+            renderer.set_solo(None);
+            (images, delay.unwrap_or(FLICKER_DELAY))
+        }
+        None => {
+            let mut images = vec![renderer.capture(size)];
+            while images.len() < frames && renderer.step() {
+                renderer.update();
+                images.push(renderer.capture(size));
+            }
+            let frame_rate = renderer.frame_rate();
+            let frame_time = if frame_rate.numerator() > 0 && frame_rate.denominator() > 0 {
+                (1000.0 / f64::from(frame_rate)).round() as u32
+            } else {
+                DEFAULT_FRAME_DELAY
+            };
+            (images, delay.unwrap_or(frame_time))
+        }
+    };
+
+    let count = images.len();
+    if gif {
+        write_gif(path, size, images, delay)?;
+    } else {
+        write_apng(path, size, &images, delay);
+    }
+    println!("wrote {} frames to {}", count, output);
+    Ok(())
+}
+
+/// `size` as GIFs store it.
+fn gif_size(size: (u32, u32)) -> Result<(u16, u16), String> {
+    match (u16::try_from(size.0), u16::try_from(size.1)) {
+        (Ok(width), Ok(height)) => Ok((width, height)),
+        _ => Err(format!(
+            "{}x{} is too large for a gif, which is at most 65535x65535",
+            size.0, size.1
+        )),
+    }
+}
+
+/// Write looping `images` of tightly packed RGBA rows shown `delay`
+/// milliseconds each. Fails if `size` is too large for a GIF.
+pub fn write_gif(
+    path: &Path,
+    size: (u32, u32),
+    images: Vec<Vec<u8>>,
+    delay: u32,
+) -> Result<(), String> {
+    let (width, height) = gif_size(size)?;
+    let file = std::fs::File::create(path).expect("to create the gif");
+    let mut encoder = gif::Encoder::new(std::io::BufWriter::new(file), width, height, &[])
+        .expect("write gif header");
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .expect("make the gif loop");
+    for mut image in images {
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut image, 10);
+        // GIF delays are in hundredths of a second, most viewers treat
+        // anything below 2 as a default that is much slower.
+        frame.delay = (delay / 10).clamp(2, u16::MAX as u32) as u16;
+        encoder.write_frame(&frame).expect("write gif frame");
+    }
+    Ok(())
+}
+
+/// Write looping `images` of tightly packed RGBA rows shown `delay`
+/// milliseconds each.
+pub fn write_apng(path: &Path, size: (u32, u32), images: &[Vec<u8>], delay: u32) {
+    let file = std::fs::File::create(path).expect("to create the png");
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), size.0, size.1);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(images.len() as u32, 0)
+        .expect("make the png animated");
+    encoder
+        .set_frame_delay(delay.min(u16::MAX as u32) as u16, 1000)
+        .expect("set the frame delay");
+    let mut writer = encoder.write_header().expect("write png header");
+    for image in images {
+        writer.write_image_data(image).expect("write png frame");
+    }
+    writer.finish().expect("finish the png");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gif_size_fits_in_16_bits() {
+        assert_eq!(gif_size((65535, 1080)), Ok((65535, 1080)));
+        assert!(gif_size((65536, 1080)).is_err());
+        assert!(gif_size((1920, 70000)).is_err());
+    }
+}
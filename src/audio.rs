@@ -0,0 +1,517 @@
+#[cfg(feature = "audio")]
+use std::collections::VecDeque;
+#[cfg(feature = "audio")]
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ffmpeg_next::{
+    decoder, format::context::Input, format::sample, format::stream::Stream, format::Sample, frame,
+    software::resampling, ChannelLayout, Rational, Rescale,
+};
+
+/// How much audio is kept queued ahead of the video.
+const LOOKAHEAD: f64 = 0.2;
+/// How far audio and video may drift apart before the audio is resynced.
+const MAX_DRIFT: f64 = 0.08;
+
+/// Where decoded audio goes, as interleaved f32 samples.
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> u16;
+
+    /// Queue `samples` to be played after everything queued before.
+    fn play(&mut self, samples: &[f32]);
+
+    /// Time until everything queued has been played.
+    fn queued(&self) -> Duration;
+
+    /// Drop everything queued.
+    fn clear(&mut self);
+}
+
+/// A sink that plays nothing but takes as long as playing would, for tests
+/// and machines without audio.
+pub struct NullSink {
+    sample_rate: u32,
+    channels: u16,
+    playing_until: Option<Instant>,
+}
+
+impl NullSink {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            playing_until: None,
+        }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn play(&mut self, samples: &[f32]) {
+        let duration = Duration::from_secs_f64(
+            samples.len() as f64 / (self.sample_rate as f64 * self.channels as f64),
+        );
+        let now = Instant::now();
+        let start = match self.playing_until {
+            Some(until) if until > now => until,
+            _ => now,
+        };
+        self.playing_until = Some(start + duration);
+    }
+
+    fn queued(&self) -> Duration {
+        match self.playing_until {
+            Some(until) => until.saturating_duration_since(Instant::now()),
+            None => Duration::ZERO,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.playing_until = None;
+    }
+}
+
+/// The default output device of the system, or a [`NullSink`] if there is
+/// none or the crate is built without the `audio` feature.
+pub fn default_sink() -> Box<dyn AudioSink> {
+    #[cfg(feature = "audio")]
+    if let Some(sink) = DeviceSink::open() {
+        return Box::new(sink);
+    }
+    eprintln!("no audio output, playing audio silently");
+    Box::new(NullSink::new(48000, 2))
+}
+
+/// Plays through the default output device of the system.
+#[cfg(feature = "audio")]
+pub struct DeviceSink {
+    samples: Arc<Mutex<VecDeque<f32>>>,
+    sample_rate: u32,
+    channels: u16,
+    /// Playing for as long as it is kept.
+    _stream: cpal::Stream,
+}
+
+#[cfg(feature = "audio")]
+impl DeviceSink {
+    /// Open the default output device, `None` if there is none.
+    pub fn open() -> Option<Self> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+        let device = cpal::default_host().default_output_device()?;
+        let config = device.default_output_config().ok()?.config();
+        let samples = Arc::new(Mutex::new(VecDeque::new()));
+        let stream = {
+            let samples = samples.clone();
+            device
+                .build_output_stream(
+                    &config,
+                    move |output: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                        let mut samples = samples.lock().unwrap();
+                        for sample in output.iter_mut() {
+                            // Silence when running dry.
+                            *sample = samples.pop_front().unwrap_or(0.0);
+                        }
+                    },
+                    |error| eprintln!("audio output: {}", error),
+                )
+                .ok()?
+        };
+        stream.play().ok()?;
+
+        Some(Self {
+            samples,
+            sample_rate: config.sample_rate.0,
+            channels: config.channels,
+            _stream: stream,
+        })
+    }
+}
+
+#[cfg(feature = "audio")]
+impl AudioSink for DeviceSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn play(&mut self, samples: &[f32]) {
+        self.samples.lock().unwrap().extend(samples);
+    }
+
+    fn queued(&self) -> Duration {
+        let queued = self.samples.lock().unwrap().len();
+        Duration::from_secs_f64(queued as f64 / (self.sample_rate as f64 * self.channels as f64))
+    }
+
+    fn clear(&mut self) {
+        self.samples.lock().unwrap().clear();
+    }
+}
+
+/// Decodes the best audio stream of a file with ffmpeg, converted to
+/// interleaved f32 samples at the rate and channel count of a sink. Times are
+/// in seconds from the first frame of the video stream selected, as for
+/// [`crate::video::VideoStream`], so that audio starting later shows as late.
+pub struct AudioStream {
+    ictx: Input,
+    stream_index: usize,
+    decoder: decoder::Audio,
+    resampler: Option<resampling::Context>,
+    time_base: Rational,
+    /// Time of the first video frame in seconds of the audio timestamps.
+    origin: f64,
+    sample_rate: u32,
+    channels: u16,
+    /// Time of the next sample to return in seconds.
+    position: f64,
+    /// Samples before this time are dropped after seeking.
+    skip_until: f64,
+    /// Whether the end of the input was sent to the decoder, which then only
+    /// gives back the frames it still holds.
+    eof: bool,
+}
+
+impl AudioStream {
+    /// Open the audio of `path`, `None` if it has none or the input can't be
+    /// read a second time next to its video, see [`crate::source::can_reopen`].
+    pub fn open(path: &str, sample_rate: u32, channels: u16) -> Option<Self> {
+        if !crate::source::can_reopen(path) {
+            return None;
+        }
+        // The best audio stream, whichever video stream is selected.
+        let (path, selection) = crate::video::split_selection(path);
+        let ictx = ffmpeg_next::format::input(&path.to_string()).ok()?;
+        let input = ictx.streams().best(ffmpeg_next::media::Type::Audio)?;
+        let stream_index = input.index();
+        let time_base = input.time_base();
+        let start = |stream: &Stream| {
+            crate::video::start_time(stream) as f64 * f64::from(stream.time_base())
+        };
+        // Without video the audio starts at its own first frame.
+        let origin = match selection.find(&ictx).and_then(|index| ictx.stream(index)) {
+            Some(video) => start(&video),
+            None => start(&input),
+        };
+        let decoder = ffmpeg_next::codec::context::Context::from_parameters(input.parameters())
+            .ok()?
+            .decoder()
+            .audio()
+            .ok()?;
+
+        Some(Self {
+            ictx,
+            stream_index,
+            decoder,
+            resampler: None,
+            time_base,
+            origin,
+            sample_rate,
+            channels,
+            position: 0.0,
+            skip_until: 0.0,
+            eof: false,
+        })
+    }
+
+    /// Open the audio of `path` keeping the channels it has, e.g. to measure
+    /// it, `None` if it has none.
+    pub fn open_native(path: &str, sample_rate: u32) -> Option<Self> {
+        let mut stream = Self::open(path, sample_rate, 0)?;
+        stream.channels = stream.decoder.channels();
+        Some(stream)
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Next decoded samples and the time in seconds of the first one, `None`
+    /// at the end.
+    pub fn next_samples(&mut self) -> Option<(f64, Vec<f32>)> {
+        loop {
+            let decoded = self.receive_frame()?;
+            let start = match decoded.timestamp() {
+                Some(timestamp) => timestamp as f64 * f64::from(self.time_base) - self.origin,
+                None => self.position,
+            };
+            let mut samples = self.resample(&decoded);
+            let duration = samples.len() as f64 / self.samples_per_second();
+            self.position = start + duration;
+
+            if self.position <= self.skip_until {
+                continue;
+            }
+            if start < self.skip_until {
+                let skip = ((self.skip_until - start) * self.sample_rate as f64) as usize
+                    * self.channels as usize;
+                samples.drain(..skip.min(samples.len()));
+                return Some((self.skip_until, samples));
+            }
+            return Some((start, samples));
+        }
+    }
+
+    /// Continue from `seconds`.
+    pub fn seek(&mut self, seconds: f64) {
+        let timestamp = ((seconds + self.origin).max(0.0) / f64::from(self.time_base)) as i64;
+        let timestamp = timestamp.rescale(self.time_base, ffmpeg_next::rescale::TIME_BASE);
+        // Streams that can't seek carry on from where they are.
+        if self.ictx.seek(timestamp, ..timestamp).is_ok() {
+            self.decoder.flush();
+            self.eof = false;
+        }
+        self.position = seconds;
+        self.skip_until = seconds;
+    }
+
+    fn samples_per_second(&self) -> f64 {
+        self.sample_rate as f64 * self.channels as f64
+    }
+
+    fn receive_frame(&mut self) -> Option<frame::Audio> {
+        let mut decoded = frame::Audio::empty();
+        loop {
+            // Frames the decoder holds come first.
+            if self.decoder.receive_frame(&mut decoded).is_ok() {
+                return Some(decoded);
+            }
+            if self.eof {
+                return None;
+            }
+            let stream_index = self.stream_index;
+            let packet = self
+                .ictx
+                .packets()
+                .find(|(stream, _)| stream.index() == stream_index)
+                .map(|(_, packet)| packet);
+            match packet {
+                // Lost or corrupt data, carry on with the next packet.
+                Some(packet) => {
+                    let _ = self.decoder.send_packet(&packet);
+                }
+                // Get the frames still held at the end.
+                None => {
+                    let _ = self.decoder.send_eof();
+                    self.eof = true;
+                }
+            }
+        }
+    }
+
+    fn resample(&mut self, decoded: &frame::Audio) -> Vec<f32> {
+        let (sample_rate, channels) = (self.sample_rate, self.channels);
+        let decoder = &self.decoder;
+        let resampler = self.resampler.get_or_insert_with(|| {
+            let layout = decoder.channel_layout();
+            let layout = if layout.is_empty() {
+                ChannelLayout::default(decoder.channels() as i32)
+            } else {
+                layout
+            };
+            resampling::Context::get(
+                decoder.format(),
+                layout,
+                decoder.rate(),
+                Sample::F32(sample::Type::Packed),
+                ChannelLayout::default(channels as i32),
+                sample_rate,
+            )
+            .expect("audio resampler")
+        });
+
+        let mut resampled = frame::Audio::empty();
+        resampler
+            .run(decoded, &mut resampled)
+            .expect("resample audio");
+        let bytes = resampled.samples() * channels as usize * 4;
+        resampled.data(0)[..bytes]
+            .chunks_exact(4)
+            .map(|sample| f32::from_ne_bytes([sample[0], sample[1], sample[2], sample[3]]))
+            .collect()
+    }
+}
+
+/// Audio an [`AudioPlayer`] can follow the video with, see [`AudioStream`].
+trait Samples {
+    fn next_samples(&mut self) -> Option<(f64, Vec<f32>)>;
+
+    fn seek(&mut self, seconds: f64);
+}
+
+impl Samples for AudioStream {
+    fn next_samples(&mut self) -> Option<(f64, Vec<f32>)> {
+        AudioStream::next_samples(self)
+    }
+
+    fn seek(&mut self, seconds: f64) {
+        AudioStream::seek(self, seconds)
+    }
+}
+
+/// Plays the audio of one input at a time through a sink, following the time
+/// of the video frames shown.
+pub struct AudioPlayer {
+    sink: Box<dyn AudioSink>,
+    /// Index of the input heard and its audio.
+    stream: Option<(usize, Box<dyn Samples>)>,
+    /// Time in seconds right after the last sample queued.
+    queued_until: Option<f64>,
+}
+
+impl AudioPlayer {
+    pub fn new(sink: Box<dyn AudioSink>) -> Self {
+        Self {
+            sink,
+            stream: None,
+            queued_until: None,
+        }
+    }
+
+    /// Index of the input heard.
+    pub fn selected(&self) -> Option<usize> {
+        self.stream.as_ref().map(|(index, _)| *index)
+    }
+
+    /// Hear the audio of input `index` at `path`, or nothing for `None`.
+    /// Returns false if the input has no audio.
+    pub fn select(&mut self, input: Option<(usize, &str)>) -> bool {
+        self.sink.clear();
+        self.queued_until = None;
+        self.stream = input.and_then(|(index, path)| {
+            let stream = AudioStream::open(path, self.sink.sample_rate(), self.sink.channels())?;
+            Some((index, Box::new(stream) as Box<dyn Samples>))
+        });
+        input.is_none() || self.stream.is_some()
+    }
+
+    /// Keep the sink fed a little past `video_time`, the time in seconds of
+    /// the frame shown of the input heard for `frame_duration`, starting over
+    /// from there when audio and video drifted apart.
+    pub fn sync(&mut self, video_time: f64, frame_duration: f64, playing: bool) {
+        let stream = match &mut self.stream {
+            Some((_, stream)) => stream,
+            None => return,
+        };
+        if !playing {
+            self.sink.clear();
+            self.queued_until = None;
+            return;
+        }
+
+        let clock = self
+            .queued_until
+            .map(|until| until - self.sink.queued().as_secs_f64());
+        // The audio keeps going while a frame is shown.
+        let in_sync = |clock: f64| {
+            clock >= video_time - MAX_DRIFT && clock <= video_time + frame_duration + MAX_DRIFT
+        };
+        if !matches!(clock, Some(clock) if in_sync(clock)) {
+            self.sink.clear();
+            stream.seek(video_time);
+            self.queued_until = Some(video_time);
+        }
+
+        while matches!(self.queued_until, Some(until) if until < video_time + LOOKAHEAD) {
+            match stream.next_samples() {
+                Some((start, samples)) => {
+                    self.sink.play(&samples);
+                    let duration = samples.len() as f64
+                        / (self.sink.sample_rate() as f64 * self.sink.channels() as f64);
+                    self.queued_until = Some(start + duration);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const FRAME: f64 = 0.04;
+
+    /// Silence in chunks of 10 ms from wherever it was sought to, noting
+    /// every seek.
+    struct Silence {
+        position: f64,
+        seeks: Rc<RefCell<Vec<f64>>>,
+    }
+
+    impl Samples for Silence {
+        fn next_samples(&mut self) -> Option<(f64, Vec<f32>)> {
+            let start = self.position;
+            self.position += 0.01;
+            Some((start, vec![0.0; 480 * 2]))
+        }
+
+        fn seek(&mut self, seconds: f64) {
+            self.position = seconds;
+            self.seeks.borrow_mut().push(seconds);
+        }
+    }
+
+    fn player() -> (AudioPlayer, Rc<RefCell<Vec<f64>>>) {
+        let seeks = Rc::new(RefCell::new(Vec::new()));
+        let mut player = AudioPlayer::new(Box::new(NullSink::new(48000, 2)));
+        let silence = Silence {
+            position: 0.0,
+            seeks: seeks.clone(),
+        };
+        player.stream = Some((0, Box::new(silence)));
+        (player, seeks)
+    }
+
+    #[test]
+    fn starts_at_the_video_time() {
+        let (mut player, seeks) = player();
+        player.sync(5.0, FRAME, true);
+        assert_eq!(*seeks.borrow(), [5.0]);
+        let queued = player.sink.queued().as_secs_f64();
+        assert!(queued > LOOKAHEAD - 0.05 && queued < LOOKAHEAD + 0.02);
+    }
+
+    #[test]
+    fn keeps_playing_while_in_sync() {
+        let (mut player, seeks) = player();
+        player.sync(1.0, FRAME, true);
+        player.sync(1.0 + FRAME, FRAME, true);
+        assert_eq!(*seeks.borrow(), [1.0]);
+    }
+
+    #[test]
+    fn resyncs_after_drifting() {
+        let (mut player, seeks) = player();
+        player.sync(1.0, FRAME, true);
+        let ahead = 1.0 + FRAME + 2.0 * MAX_DRIFT;
+        player.sync(ahead, FRAME, true);
+        player.sync(0.5, FRAME, true);
+        assert_eq!(*seeks.borrow(), [1.0, ahead, 0.5]);
+    }
+
+    #[test]
+    fn pausing_stops_the_audio() {
+        let (mut player, seeks) = player();
+        player.sync(1.0, FRAME, true);
+        player.sync(1.0, FRAME, false);
+        assert_eq!(player.sink.queued(), Duration::ZERO);
+        player.sync(1.0, FRAME, true);
+        assert_eq!(*seeks.borrow(), [1.0, 1.0]);
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::{Arc, Mutex};
+
+use ffmpeg_next::picture;
+
+use crate::overlay::{Color, Overlay};
+use crate::video::{StreamSelection, VideoStream};
+
+/// Seconds of video shown across a strip.
+const WINDOW: f64 = 10.0;
+const STRIP_HEIGHT: f32 = 80.0;
+const TEXT_SCALE: f32 = 1.0;
+const BACKGROUND: Color = [0.0, 0.0, 0.0, 0.6];
+const INTRA: Color = [0.95, 0.3, 0.25, 0.9];
+const PREDICTED: Color = [0.3, 0.8, 0.35, 0.9];
+const BIDIRECTIONAL: Color = [0.3, 0.55, 1.0, 0.9];
+const OTHER: Color = [0.7, 0.7, 0.7, 0.9];
+const PLAYHEAD: Color = [1.0, 1.0, 1.0, 0.8];
+const TEXT: Color = [1.0, 1.0, 1.0, 1.0];
+
+#[derive(Default)]
+struct Analysis {
+    /// Size in bytes and picture type of every frame by index, `None` for
+    /// frames not seen.
+    frames: Vec<Option<(usize, picture::Type)>>,
+    frame_rate: f64,
+    /// Number of frames seen and the sum and largest of their sizes.
+    seen: usize,
+    total_size: u64,
+    largest: usize,
+    /// Whether the input can be gone over, unlike pipes and live streams.
+    readable: bool,
+    done: bool,
+}
+
+/// The size and picture type of every frame of the videos, read on
+/// background threads, to follow their bitrate over time.
+pub struct Bitrates {
+    analyses: Vec<Arc<Mutex<Analysis>>>,
+}
+
+impl Bitrates {
+    /// Go over video stream `stream` of the file at `path` for every input,
+    /// skipping inputs that are `None`.
+    pub fn spawn(inputs: &[Option<(String, usize)>]) -> Self {
+        Self {
+            analyses: inputs.iter().cloned().map(spawn).collect(),
+        }
+    }
+
+    /// Start over for input `index` after it switched to another stream.
+    pub fn input_changed(&mut self, index: usize, input: Option<(String, usize)>) {
+        if let Some(analysis) = self.analyses.get_mut(index) {
+            *analysis = spawn(input);
+        }
+    }
+
+    /// Add a strip for every input in a target of `size` ending at `bottom`,
+    /// each centred on `frames`, the index of the frame shown of the input.
+    /// Returns the top of the strips for what goes above.
+    pub fn draw(
+        &self,
+        overlay: &mut Overlay,
+        size: (u32, u32),
+        bottom: f32,
+        frames: &[usize],
+    ) -> f32 {
+        let count = self.analyses.len();
+        let top = bottom - STRIP_HEIGHT;
+        let width = size.0 as f32 / count as f32;
+        let analyses = self
+            .analyses
+            .iter()
+            .map(|analysis| analysis.lock().unwrap())
+            .collect::<Vec<_>>();
+        // The same scale for all, to compare them.
+        let largest = analyses
+            .iter()
+            .map(|analysis| analysis.largest)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+
+        for (index, (analysis, &frame)) in analyses.iter().zip(frames).enumerate() {
+            let left = index as f32 * width;
+            overlay.rect(left + 1.0, top, width - 2.0, STRIP_HEIGHT, BACKGROUND);
+            if !analysis.readable {
+                overlay.text(left + 4.0, top + 4.0, TEXT_SCALE, "no bitrate", TEXT);
+                continue;
+            }
+
+            let shown = WINDOW * analysis.frame_rate;
+            let first = frame as f64 - shown / 2.0;
+            let per_pixel = shown / width as f64;
+            for x in 0..width as usize {
+                let start = first + x as f64 * per_pixel;
+                let end = start + per_pixel.max(1.0);
+                if start < 0.0 {
+                    continue;
+                }
+                // The largest frame under the pixel.
+                let largest_frame = analysis
+                    .frames
+                    .get(start as usize..(end as usize).min(analysis.frames.len()))
+                    .and_then(|frames| frames.iter().flatten().max_by_key(|(size, _)| *size));
+                if let Some(&(frame_size, kind)) = largest_frame {
+                    let height = (frame_size as f32 / largest as f32 * STRIP_HEIGHT).max(1.0);
+                    overlay.rect(left + x as f32, bottom - height, 1.0, height, color(kind));
+                }
+            }
+            overlay.rect(left + width / 2.0, top, 1.0, STRIP_HEIGHT, PLAYHEAD);
+
+            let text = format!(
+                "{:.0} kb/s average  {:.0} kb/s last second{}",
+                average(analysis) / 1000.0,
+                last_second(analysis, frame) / 1000.0,
+                if analysis.done { "" } else { "  analysing" },
+            );
+            overlay.text(left + 4.0, top + 4.0, TEXT_SCALE, &text, TEXT);
+        }
+        top
+    }
+}
+
+fn spawn(input: Option<(String, usize)>) -> Arc<Mutex<Analysis>> {
+    let analysis = Arc::new(Mutex::new(Analysis::default()));
+    match input {
+        Some((path, stream)) => {
+            let thread_analysis = analysis.clone();
+            std::thread::spawn(move || analyse(&path, stream, &thread_analysis));
+        }
+        None => analysis.lock().unwrap().done = true,
+    }
+    analysis
+}
+
+/// Go over the frames of video stream `stream` of `path` into `analysis`.
+fn analyse(path: &str, stream: usize, analysis: &Mutex<Analysis>) {
+    let (path, _) = crate::video::split_selection(path);
+    let video = VideoStream::decoded(path, &StreamSelection::Index(stream));
+    {
+        let mut analysis = analysis.lock().unwrap();
+        analysis.frame_rate = f64::from(video.frame_rate());
+        analysis.readable = true;
+    }
+
+    for frame in video.coded_frames() {
+        let mut analysis = analysis.lock().unwrap();
+        if analysis.frames.len() <= frame.index {
+            analysis.frames.resize(frame.index + 1, None);
+        }
+        // Frames with the same index, as with variable frame rates, add up.
+        let size = match analysis.frames[frame.index] {
+            Some((size, _)) => size + frame.size,
+            None => frame.size,
+        };
+        analysis.frames[frame.index] = Some((size, frame.kind));
+        analysis.seen += 1;
+        analysis.total_size += frame.size as u64;
+        analysis.largest = analysis.largest.max(size);
+    }
+    analysis.lock().unwrap().done = true;
+}
+
+fn color(kind: picture::Type) -> Color {
+    match kind {
+        picture::Type::I | picture::Type::SI => INTRA,
+        picture::Type::P | picture::Type::SP => PREDICTED,
+        picture::Type::B | picture::Type::BI => BIDIRECTIONAL,
+        _ => OTHER,
+    }
+}
+
+/// Bits per second over all frames seen.
+fn average(analysis: &Analysis) -> f64 {
+    if analysis.seen == 0 {
+        return 0.0;
+    }
+    analysis.total_size as f64 * 8.0 * analysis.frame_rate / analysis.seen as f64
+}
+
+/// Bits per second over the second of frames up to `frame`.
+fn last_second(analysis: &Analysis, frame: usize) -> f64 {
+    let frames = (analysis.frame_rate.round() as usize).max(1);
+    let start = (frame + 1).saturating_sub(frames);
+    let end = (frame + 1).min(analysis.frames.len());
+    let size = analysis
+        .frames
+        .get(start..end)
+        .map(|frames| frames.iter().flatten().map(|(size, _)| size).sum::<usize>())
+        .unwrap_or(0);
+    size as f64 * 8.0 * analysis.frame_rate / frames as f64
+}
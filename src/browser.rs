@@ -0,0 +1,210 @@
+use std::io;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+
+use ffmpeg_next::{format::Pixel, util::frame::Video, Rational};
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::{Message, WebSocket};
+
+use crate::live::{Live, LiveStatus};
+use crate::server::{self, Role};
+use crate::source::FrameSource;
+
+/// Scheme of inputs received from the camera of a browser, followed by the
+/// address to serve the client page at.
+pub const SCHEME: &str = "webcam://";
+
+/// Kinds of frames sent by the client, the first byte of every message.
+const JPEG: u8 = 0;
+const RGBA: u8 = 1;
+/// Kind, timestamp in milliseconds as f64, width and height as u32, all
+/// little endian, followed by the image.
+const HEADER_SIZE: usize = 17;
+
+/// A frame sent by a client with its timestamp in seconds.
+struct Received {
+    timestamp: f64,
+    frame: Video,
+}
+
+/// Frames received but not shown yet, more are skipped.
+const QUEUED_FRAMES: usize = 2;
+/// Size of the black frame shown until the camera sends one.
+const WAITING_SIZE: (u32, u32) = (640, 480);
+
+/// Frames of a browser camera, streamed by the page in client/. Every call
+/// for the next frame takes the newest one received, skipping the rest, and
+/// shows the last one again when none arrived, counting it as dropped, as
+/// when the tab is closed. Only one camera is taken at a time.
+pub struct BrowserCamera {
+    frames: Receiver<Received>,
+    /// The frame shown most recently, black until the camera sends one.
+    last: Video,
+    frame_rate: Rational,
+    live: Live,
+    /// Timestamp of the first frame in seconds, on the clock of the client.
+    start: Option<f64>,
+    timestamp: f64,
+    frame_index: Option<usize>,
+}
+
+impl BrowserCamera {
+    /// Serve the client at `address` in the background. Frames are played
+    /// back at `frame_rate`.
+    pub fn serve(address: &str, frame_rate: Rational) -> io::Result<Self> {
+        let (sender, frames) = mpsc::sync_channel(QUEUED_FRAMES);
+        let connected = Arc::new(AtomicBool::new(false));
+        server::spawn(address, Role::Camera, move |socket| {
+            // Frames of another camera would be mixed in with the first.
+            if connected.swap(true, Ordering::SeqCst) {
+                reject(socket);
+                return;
+            }
+            receive(socket, &sender);
+            connected.store(false, Ordering::SeqCst);
+        })?;
+        println!("waiting for a camera at http://{}/", address);
+
+        let mut waiting = Video::new(Pixel::GRAY8, WAITING_SIZE.0, WAITING_SIZE.1);
+        waiting.data_mut(0).fill(0);
+        Ok(Self {
+            frames,
+            last: waiting,
+            frame_rate,
+            live: Live::new(),
+            start: None,
+            timestamp: 0.0,
+            frame_index: None,
+        })
+    }
+}
+
+impl FrameSource for BrowserCamera {
+    fn next_frame(&mut self) -> Option<Video> {
+        let mut newest = None;
+        while let Ok(received) = self.frames.try_recv() {
+            if newest.is_some() {
+                self.live.status.dropped += 1;
+            }
+            newest = Some(received);
+        }
+
+        match newest {
+            Some(received) => {
+                let start = *self.start.get_or_insert(received.timestamp);
+                self.live.frame(received.timestamp);
+                self.timestamp = received.timestamp - start;
+                self.last = received.frame;
+            }
+            None if self.start.is_some() => self.live.status.dropped += 1,
+            // Still waiting for the camera.
+            None => {}
+        }
+        self.frame_index = Some(self.frame_index.map_or(0, |index| index + 1));
+        Some(self.last.clone())
+    }
+
+    fn seek(&mut self, index: usize) -> Option<Video> {
+        // Frames arrive live so only later ones can be reached, see
+        // crate::pipe::Piped for seeking back.
+        loop {
+            let frame = self.next_frame()?;
+            if self.frame_index() >= index {
+                return Some(frame);
+            }
+        }
+    }
+
+    fn frame_index(&self) -> usize {
+        self.frame_index.unwrap_or(0)
+    }
+
+    fn frame_rate(&self) -> Rational {
+        self.frame_rate
+    }
+
+    fn timestamp(&self) -> f64 {
+        self.timestamp
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.last.width(), self.last.height())
+    }
+
+    fn format(&self) -> Pixel {
+        self.last.format()
+    }
+
+    fn live_status(&self) -> Option<LiveStatus> {
+        Some(self.live.status)
+    }
+}
+
+/// Pass on the frames of the camera at `socket`, skipping them while
+/// [`QUEUED_FRAMES`] wait to be shown.
+fn receive(mut socket: WebSocket<TcpStream>, frames: &SyncSender<Received>) {
+    loop {
+        match socket.read_message() {
+            Ok(Message::Binary(message)) => {
+                if let Some(received) = decode(&message) {
+                    if let Err(TrySendError::Disconnected(_)) = frames.try_send(received) {
+                        return;
+                    }
+                }
+            }
+            Ok(Message::Close(_)) | Err(_) => return,
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Turn away a camera while another one is connected.
+fn reject(mut socket: WebSocket<TcpStream>) {
+    let close = CloseFrame {
+        code: CloseCode::Policy,
+        reason: "another camera is connected".into(),
+    };
+    // The client may be gone already.
+    let _ = socket.close(Some(close));
+    let _ = socket.write_pending();
+}
+
+/// Decode a message of the client, `None` if it is broken.
+fn decode(message: &[u8]) -> Option<Received> {
+    if message.len() < HEADER_SIZE {
+        return None;
+    }
+    let timestamp = f64::from_le_bytes(message[1..9].try_into().ok()?) / 1000.0;
+    let width = u32::from_le_bytes(message[9..13].try_into().ok()?);
+    let height = u32::from_le_bytes(message[13..17].try_into().ok()?);
+    let image = &message[HEADER_SIZE..];
+
+    let frame = match message[0] {
+        JPEG => {
+            let mut decoder = jpeg_decoder::Decoder::new(image);
+            let pixels = decoder.decode().ok()?;
+            let info = decoder.info()?;
+            let format = match info.pixel_format {
+                jpeg_decoder::PixelFormat::L8 => Pixel::GRAY8,
+                jpeg_decoder::PixelFormat::RGB24 => Pixel::RGB24,
+                _ => return None,
+            };
+            pack(format, (info.width as u32, info.height as u32), &pixels)?
+        }
+        RGBA => pack(Pixel::RGBA, (width, height), image)?,
+        _ => return None,
+    };
+    Some(Received { timestamp, frame })
+}
+
+/// Frame of the tightly packed rows of `pixels`.
+fn pack(format: Pixel, dimensions: (u32, u32), pixels: &[u8]) -> Option<Video> {
+    let row_sizes = crate::raw::row_sizes(format, dimensions);
+    if pixels.len() < crate::raw::frame_size(format, dimensions, &row_sizes) {
+        return None;
+    }
+    Some(crate::raw::unpack(format, dimensions, &row_sizes, pixels))
+}
@@ -0,0 +1,240 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ffmpeg_next::{color, format::Pixel, software::scaling::Context, util::frame::Video};
+
+/// Number of captures written by this process, so that captures within the
+/// same millisecond get different names.
+static CAPTURES: AtomicUsize = AtomicUsize::new(0);
+
+/// Texture the composite can be rendered into instead of the window surface.
+pub fn create_target(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    size: (u32, u32),
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Capture target"),
+        size: wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    })
+}
+
+/// Copy `texture` back to the CPU as tightly packed RGBA rows, waiting for the
+/// GPU to finish.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    size: (u32, u32),
+) -> Vec<u8> {
+    let unpadded_bytes_per_row = 4 * size.0;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padding = (align - unpadded_bytes_per_row % align) % align;
+    let padded_bytes_per_row = unpadded_bytes_per_row + padding;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Capture buffer"),
+        size: (padded_bytes_per_row * size.1) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Capture Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                rows_per_image: std::num::NonZeroU32::new(size.1),
+            },
+        },
+        wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).unwrap();
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv().unwrap().expect("to map the capture buffer");
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * size.1) as usize);
+    {
+        let data = slice.get_mapped_range();
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    buffer.unmap();
+
+    if matches!(
+        format,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+    ) {
+        pixels.chunks_mut(4).for_each(|pixel| pixel.swap(0, 2));
+    }
+    pixels
+}
+
+pub fn write_png(path: &Path, size: (u32, u32), rgba: &[u8]) -> io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut encoder = png::Encoder::new(io::BufWriter::new(file), size.0, size.1);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    Ok(())
+}
+
+/// Write a decoded `frame` at its native size as an RGB PNG, with 16 bits per
+/// channel if the source has more than 8 bits per component.
+pub fn write_frame_png(path: &Path, frame: &Video) -> io::Result<()> {
+    let high_bit_depth = match frame.format().descriptor() {
+        Some(descriptor) => unsafe { (*descriptor.as_ptr()).comp[0].depth > 8 },
+        None => false,
+    };
+    // PNG stores 16 bit samples big endian, same as RGB48BE.
+    let (format, depth, bytes_per_pixel) = if high_bit_depth {
+        (Pixel::RGB48BE, png::BitDepth::Sixteen, 6)
+    } else {
+        (Pixel::RGB24, png::BitDepth::Eight, 3)
+    };
+
+    let mut scaler = Context::get(
+        frame.format(),
+        frame.width(),
+        frame.height(),
+        format,
+        frame.width(),
+        frame.height(),
+        ffmpeg_next::software::scaling::flag::Flags::POINT,
+    )
+    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    // The scaler assumes BT.601 in limited range unless told otherwise.
+    unsafe {
+        let coefficients = ffmpeg_next::ffi::sws_getCoefficients(
+            ffmpeg_next::ffi::AVColorSpace::from(frame.color_space()) as i32,
+        );
+        ffmpeg_next::ffi::sws_setColorspaceDetails(
+            scaler.as_mut_ptr(),
+            coefficients,
+            (frame.color_range() == color::Range::JPEG) as i32,
+            coefficients,
+            1,
+            0,
+            1 << 16,
+            1 << 16,
+        );
+    }
+    let mut rgb = Video::empty();
+    scaler
+        .run(frame, &mut rgb)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+    let row_bytes = rgb.width() as usize * bytes_per_pixel;
+    let pixels = rgb
+        .data(0)
+        .chunks(rgb.stride(0))
+        .take(rgb.height() as usize)
+        .flat_map(|row| &row[..row_bytes])
+        .copied()
+        .collect::<Vec<_>>();
+
+    let file = std::fs::File::create(path)?;
+    let mut encoder = png::Encoder::new(io::BufWriter::new(file), rgb.width(), rgb.height());
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(depth);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    Ok(())
+}
+
+/// Path next to `input` for an exported source frame, named after the input,
+/// `frame` and the time of the export, in the current directory for inputs
+/// that aren't files.
+pub fn frame_path(input: &str, frame: usize) -> PathBuf {
+    let name = |stem: &str| format!("{}-{}-frame{:06}.png", stem, unique_id(), frame);
+    match input_file(input) {
+        Some(input) => {
+            let stem = input
+                .file_stem()
+                .map_or_else(|| "frame".into(), |stem| stem.to_string_lossy());
+            input.with_file_name(name(&stem))
+        }
+        None => PathBuf::from(name("frame")),
+    }
+}
+
+/// Path in the directory of `input` for a capture named after the current
+/// time, `frame` and `suffix`, in the current directory for inputs that
+/// aren't files.
+pub fn output_path(input: &str, frame: usize, suffix: &str) -> PathBuf {
+    let directory = input_file(input)
+        .and_then(Path::parent)
+        .filter(|directory| !directory.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    directory.join(format!(
+        "quick-compare-{}-frame{}{}",
+        unique_id(),
+        frame,
+        suffix
+    ))
+}
+
+/// The file or directory `input` is read from, `None` for URLs, pipes and
+/// anything else that isn't stored there.
+fn input_file(input: &str) -> Option<&Path> {
+    let (path, _) = crate::video::split_selection(input);
+    let path = Path::new(path);
+    (crate::source::can_reopen(input) && path.exists()).then_some(path)
+}
+
+/// Milliseconds since the epoch and the number of the capture, different
+/// for every capture of this process.
+fn unique_id() -> String {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("time after the epoch")
+        .as_millis();
+    format!("{}-{}", timestamp, CAPTURES.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Parse a size written as `WIDTHxHEIGHT`.
+pub fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let (width, height) = size
+        .split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {}", size))?;
+    let parse = |value: &str| {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|&value| value > 0)
+            .ok_or_else(|| format!("invalid dimension {}", value))
+    };
+    Ok((parse(width)?, parse(height)?))
+}
@@ -0,0 +1,434 @@
+use std::io::Write;
+
+use ffmpeg_next::{format::Pixel, util::frame::Video};
+
+use crate::metrics::{Metrics, Summary};
+use crate::source::{self, Converted, InputOptions};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum OutputFormat {
+    Csv,
+    Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThresholdScope {
+    /// every single frame has to pass
+    Frame,
+    /// the mean over all frames has to pass
+    Mean,
+}
+
+/// Picks a single value out of the metrics of a frame.
+type MetricValue = fn(&Metrics) -> f64;
+
+/// Minimum quality the compared videos must reach for the comparison to pass.
+#[derive(clap::Args, Debug)]
+pub struct Thresholds {
+    /// fail when luma PSNR is below this many dB
+    #[clap(long)]
+    min_psnr: Option<f64>,
+    /// fail when SSIM is below this
+    #[clap(long)]
+    min_ssim: Option<f64>,
+    /// fail when MS-SSIM is below this
+    #[clap(long)]
+    min_ms_ssim: Option<f64>,
+    /// whether the thresholds apply to every frame or to the mean
+    #[clap(long, value_enum, default_value = "frame")]
+    threshold_scope: ThresholdScope,
+}
+
+impl Thresholds {
+    fn limits(&self) -> Vec<(&'static str, f64, MetricValue)> {
+        let limits: [(&'static str, Option<f64>, MetricValue); 3] = [
+            ("PSNR Y", self.min_psnr, |metrics| metrics.psnr.y),
+            ("SSIM", self.min_ssim, |metrics| metrics.ssim),
+            ("MS-SSIM", self.min_ms_ssim, |metrics| metrics.ms_ssim),
+        ];
+        limits
+            .into_iter()
+            .filter_map(|(name, limit, value)| Some((name, limit?, value)))
+            .collect()
+    }
+
+    /// Describe every way `report` falls short of the thresholds.
+    fn violations(&self, report: &Report) -> Vec<String> {
+        let mut violations = Vec::new();
+        for input in &report.inputs {
+            for (name, limit, value) in self.limits() {
+                match self.threshold_scope {
+                    ThresholdScope::Frame => {
+                        let frames = input
+                            .frames
+                            .iter()
+                            .filter(|result| value(&result.metrics) < limit)
+                            .map(|result| result.frame)
+                            .collect::<Vec<_>>();
+                        if !frames.is_empty() {
+                            violations.push(format!(
+                                "{}: {} below {} in frames {}",
+                                input.path,
+                                name,
+                                limit,
+                                frame_ranges(&frames)
+                            ));
+                        }
+                    }
+                    ThresholdScope::Mean => {
+                        let mean = value(&input.summary.mean);
+                        if mean < limit {
+                            violations.push(format!(
+                                "{}: mean {} {:.4} below {}",
+                                input.path, name, mean, limit
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        violations
+    }
+}
+
+#[derive(serde::Serialize)]
+struct FrameResult {
+    frame: usize,
+    #[serde(flatten)]
+    metrics: Metrics,
+}
+
+#[derive(serde::Serialize)]
+struct InputResult {
+    path: String,
+    frames: Vec<FrameResult>,
+    summary: Summary,
+}
+
+#[derive(serde::Serialize)]
+struct Report {
+    reference: String,
+    inputs: Vec<InputResult>,
+}
+
+/// Decode `videos` without opening a window and compare every video after the
+/// first against the first one, frame by frame. Returns whether all videos
+/// passed `thresholds`, listing the failures on stderr.
+pub fn run(
+    videos: &[String],
+    format: OutputFormat,
+    output: Option<&str>,
+    thresholds: &Thresholds,
+    options: &InputOptions,
+) -> bool {
+    let (reference_path, paths) = videos.split_first().expect("a reference video");
+    let report = compare(reference_path, paths, options);
+
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(std::fs::File::create(path).expect("to create the output file")),
+        None => Box::new(std::io::stdout()),
+    };
+    match format {
+        OutputFormat::Csv => write_csv(&report, &mut writer),
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &report).expect("write json");
+            writeln!(writer).expect("write json");
+        }
+    }
+
+    let violations = thresholds.violations(&report);
+    for violation in &violations {
+        eprintln!("{}", violation);
+    }
+    violations.is_empty()
+}
+
+fn compare(reference_path: &str, paths: &[String], options: &InputOptions) -> Report {
+    let mut reference = Converted::new(source::open(reference_path, options), Pixel::YUV420P, None);
+    // Everything is scaled to the reference so inputs of different sizes can
+    // still be compared.
+    let dimensions = reference.dimensions();
+    let mut streams = paths
+        .iter()
+        .map(|path| {
+            Converted::new(
+                source::open(path, options),
+                Pixel::YUV420P,
+                Some(dimensions),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut frames = paths.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+
+    pair_frames(
+        &mut reference,
+        &mut streams,
+        |frame, reference_frame, distorted_frames| {
+            for (results, distorted_frame) in frames.iter_mut().zip(distorted_frames) {
+                results.push(FrameResult {
+                    frame,
+                    metrics: Metrics::from_frames(reference_frame, distorted_frame),
+                });
+            }
+        },
+    );
+
+    Report {
+        reference: reference_path.to_string(),
+        inputs: paths
+            .iter()
+            .zip(frames)
+            .map(|(path, frames)| {
+                let metrics = frames
+                    .iter()
+                    .map(|result| result.metrics)
+                    .collect::<Vec<_>>();
+                InputResult {
+                    path: path.clone(),
+                    summary: Summary::from_frames(&metrics),
+                    frames,
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Frames read in step from the reference and the inputs compared to it.
+trait Frames {
+    type Frame;
+
+    fn next_frame(&mut self) -> Option<Self::Frame>;
+    fn is_still(&self) -> bool;
+}
+
+impl Frames for Converted {
+    type Frame = Video;
+
+    fn next_frame(&mut self) -> Option<Video> {
+        Converted::next_frame(self)
+    }
+
+    fn is_still(&self) -> bool {
+        Converted::is_still(self)
+    }
+}
+
+/// Call `compare` with the index, the reference frame and the frames of
+/// `inputs` for every frame they all have, until the shortest one ends.
+/// Returns the number of frames compared.
+fn pair_frames<S: Frames>(
+    reference: &mut S,
+    inputs: &mut [S],
+    mut compare: impl FnMut(usize, &S::Frame, &[S::Frame]),
+) -> usize {
+    let mut frame = 0;
+    'frames: while let Some(reference_frame) = reference.next_frame() {
+        let mut input_frames = Vec::with_capacity(inputs.len());
+        for input in inputs.iter_mut() {
+            match input.next_frame() {
+                Some(input_frame) => input_frames.push(input_frame),
+                None => break 'frames,
+            }
+        }
+
+        compare(frame, &reference_frame, &input_frames);
+        frame += 1;
+        // Stills never end, comparing them once is enough.
+        if reference.is_still() && inputs.iter().all(S::is_still) {
+            break;
+        }
+    }
+    frame
+}
+
+fn write_csv(report: &Report, writer: &mut impl Write) {
+    writeln!(writer, "input,frame,{}", Metrics::NAMES.join(",")).expect("write csv");
+    for input in &report.inputs {
+        let summary = &input.summary;
+        let rows = input
+            .frames
+            .iter()
+            .map(|result| (result.frame.to_string(), &result.metrics))
+            .chain([
+                ("mean".to_string(), &summary.mean),
+                ("min".to_string(), &summary.min),
+                ("p1".to_string(), &summary.p1),
+                ("p5".to_string(), &summary.p5),
+                ("p50".to_string(), &summary.p50),
+            ]);
+        for (frame, metrics) in rows {
+            let values = metrics
+                .values()
+                .iter()
+                .map(|value| format!("{:.4}", value))
+                .collect::<Vec<_>>();
+            writeln!(writer, "{},{},{}", input.path, frame, values.join(",")).expect("write csv");
+        }
+    }
+}
+
+/// Format sorted frame indices as ranges, e.g. `3-5, 9, 12-13`.
+fn frame_ranges(frames: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &frame in frames {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == frame => *end = frame,
+            _ => ranges.push((frame, frame)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames numbered from 0 up to `length`, or frame 0 forever for a still.
+    struct Counter {
+        next: usize,
+        length: Option<usize>,
+    }
+
+    impl Counter {
+        fn new(length: usize) -> Self {
+            Self {
+                next: 0,
+                length: Some(length),
+            }
+        }
+
+        fn still() -> Self {
+            Self {
+                next: 0,
+                length: None,
+            }
+        }
+    }
+
+    impl Frames for Counter {
+        type Frame = usize;
+
+        fn next_frame(&mut self) -> Option<usize> {
+            match self.length {
+                Some(length) if self.next >= length => None,
+                Some(_) => {
+                    self.next += 1;
+                    Some(self.next - 1)
+                }
+                None => Some(0),
+            }
+        }
+
+        fn is_still(&self) -> bool {
+            self.length.is_none()
+        }
+    }
+
+    fn paired(mut reference: Counter, mut inputs: Vec<Counter>) -> Vec<(usize, usize, Vec<usize>)> {
+        let mut pairs = Vec::new();
+        let count = pair_frames(&mut reference, &mut inputs, |frame, reference, inputs| {
+            pairs.push((frame, *reference, inputs.to_vec()))
+        });
+        assert_eq!(count, pairs.len());
+        pairs
+    }
+
+    #[test]
+    fn pairs_until_the_shortest_input_ends() {
+        let pairs = paired(Counter::new(5), vec![Counter::new(3), Counter::new(8)]);
+        assert_eq!(
+            pairs,
+            vec![(0, 0, vec![0, 0]), (1, 1, vec![1, 1]), (2, 2, vec![2, 2])]
+        );
+        assert_eq!(paired(Counter::new(2), vec![Counter::new(4)]).len(), 2);
+        assert!(paired(Counter::new(3), vec![Counter::new(0)]).is_empty());
+    }
+
+    #[test]
+    fn pairs_stills_with_every_frame_of_videos() {
+        assert_eq!(paired(Counter::still(), vec![Counter::new(4)]).len(), 4);
+        assert_eq!(paired(Counter::new(4), vec![Counter::still()]).len(), 4);
+        assert_eq!(paired(Counter::still(), vec![Counter::still()]).len(), 1);
+    }
+
+    fn report(psnrs: &[f64]) -> Report {
+        let frames = psnrs
+            .iter()
+            .enumerate()
+            .map(|(frame, &y)| FrameResult {
+                frame,
+                metrics: Metrics {
+                    psnr: crate::metrics::Psnr {
+                        y,
+                        ..Default::default()
+                    },
+                    ssim: 1.0,
+                    ms_ssim: 1.0,
+                },
+            })
+            .collect::<Vec<_>>();
+        let metrics = frames
+            .iter()
+            .map(|result| result.metrics)
+            .collect::<Vec<_>>();
+        Report {
+            reference: "reference.mp4".to_string(),
+            inputs: vec![InputResult {
+                path: "distorted.mp4".to_string(),
+                summary: Summary::from_frames(&metrics),
+                frames,
+            }],
+        }
+    }
+
+    fn thresholds(min_psnr: Option<f64>, threshold_scope: ThresholdScope) -> Thresholds {
+        Thresholds {
+            min_psnr,
+            min_ssim: None,
+            min_ms_ssim: None,
+            threshold_scope,
+        }
+    }
+
+    #[test]
+    fn passes_without_thresholds() {
+        let report = report(&[10.0, 20.0]);
+        assert!(thresholds(None, ThresholdScope::Frame)
+            .violations(&report)
+            .is_empty());
+    }
+
+    #[test]
+    fn fails_on_frames_below_threshold() {
+        let report = report(&[40.0, 30.0, 31.0, 45.0, 20.0]);
+        assert_eq!(
+            thresholds(Some(35.0), ThresholdScope::Frame).violations(&report),
+            vec!["distorted.mp4: PSNR Y below 35 in frames 1-2, 4".to_string()]
+        );
+        assert!(thresholds(Some(20.0), ThresholdScope::Frame)
+            .violations(&report)
+            .is_empty());
+    }
+
+    #[test]
+    fn fails_on_mean_below_threshold() {
+        let report = report(&[40.0, 30.0]);
+        assert!(thresholds(Some(35.0), ThresholdScope::Mean)
+            .violations(&report)
+            .is_empty());
+        assert_eq!(
+            thresholds(Some(36.0), ThresholdScope::Mean).violations(&report),
+            vec!["distorted.mp4: mean PSNR Y 35.0000 below 36".to_string()]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::{Arc, Mutex};
+
+use ffmpeg_next::format::Pixel;
+
+use crate::metrics::{self, Plane};
+use crate::overlay::{Color, Overlay};
+use crate::source::{self, Converted, InputOptions};
+
+const TEXT_SCALE: f32 = 2.0;
+const BACKGROUND: Color = [0.0, 0.0, 0.0, 0.6];
+const TEXT: Color = [1.0, 1.0, 1.0, 1.0];
+
+#[derive(Default)]
+struct Scan {
+    /// Luma PSNR of every frame scanned so far, by frame index.
+    scores: Vec<f64>,
+    done: bool,
+}
+
+/// Per-frame difference scores between two videos, computed on a background
+/// thread so the viewer can jump between the frames that differ the most.
+pub struct Differences {
+    scan: Arc<Mutex<Scan>>,
+    threshold: f64,
+    worst: usize,
+    /// Position in the list of worst frames of the last jump to one of them.
+    worst_position: Option<usize>,
+}
+
+impl Differences {
+    /// Start scanning `distorted` against `reference`, both opened with
+    /// `options`. Frames with a luma PSNR below `threshold` count as different.
+    pub fn spawn(
+        reference: String,
+        distorted: String,
+        options: InputOptions,
+        threshold: f64,
+        worst: usize,
+    ) -> Self {
+        let scan = Arc::new(Mutex::new(Scan::default()));
+        let thread_scan = scan.clone();
+        std::thread::spawn(move || {
+            let mut reference =
+                Converted::new(source::open(&reference, &options), Pixel::YUV420P, None);
+            let dimensions = reference.dimensions();
+            let mut distorted = Converted::new(
+                source::open(&distorted, &options),
+                Pixel::YUV420P,
+                Some(dimensions),
+            );
+
+            while let (Some(reference_frame), Some(distorted_frame)) =
+                (reference.next_frame(), distorted.next_frame())
+            {
+                let score = metrics::psnr(metrics::mse(
+                    Plane::from_frame(&reference_frame, 0),
+                    Plane::from_frame(&distorted_frame, 0),
+                ));
+                thread_scan.lock().unwrap().scores.push(score);
+                if reference.is_still() && distorted.is_still() {
+                    break;
+                }
+            }
+            thread_scan.lock().unwrap().done = true;
+        });
+
+        Self {
+            scan,
+            threshold,
+            worst,
+            worst_position: None,
+        }
+    }
+
+    /// First different frame after `frame`.
+    pub fn next(&self, frame: usize) -> Option<usize> {
+        let scan = self.scan.lock().unwrap();
+        scan.scores
+            .iter()
+            .enumerate()
+            .skip(frame + 1)
+            .find(|(_, &score)| score < self.threshold)
+            .map(|(index, _)| index)
+    }
+
+    /// Last different frame before `frame`.
+    pub fn previous(&self, frame: usize) -> Option<usize> {
+        let scan = self.scan.lock().unwrap();
+        scan.scores
+            .iter()
+            .enumerate()
+            .take(frame)
+            .rev()
+            .find(|(_, &score)| score < self.threshold)
+            .map(|(index, _)| index)
+    }
+
+    /// Cycle through the worst frames scanned so far, worst first.
+    pub fn next_worst(&mut self) -> Option<usize> {
+        let scan = self.scan.lock().unwrap();
+        let mut frames = (0..scan.scores.len()).collect::<Vec<_>>();
+        frames.sort_by(|&a, &b| scan.scores[a].total_cmp(&scan.scores[b]));
+        frames.truncate(self.worst);
+        if frames.is_empty() {
+            return None;
+        }
+
+        let position = self
+            .worst_position
+            .map_or(0, |position| (position + 1) % frames.len());
+        self.worst_position = Some(position);
+        Some(frames[position])
+    }
+
+    /// Add the progress of the scan and the score of `frame` to the top
+    /// right corner of `overlay`.
+    pub fn draw(&self, overlay: &mut Overlay, size: (u32, u32), frame: usize) {
+        let scan = self.scan.lock().unwrap();
+        let different = scan
+            .scores
+            .iter()
+            .filter(|&&score| score < self.threshold)
+            .count();
+        let mut text = format!(
+            "{} {} frames, {} below {} dB",
+            if scan.done { "scanned" } else { "scanning" },
+            scan.scores.len(),
+            different,
+            self.threshold
+        );
+        if let Some(score) = scan.scores.get(frame) {
+            text = format!("frame {}: {:.2} dB  {}", frame, score, text);
+        }
+
+        let (width, height) = Overlay::text_size(&text, TEXT_SCALE);
+        let left = size.0 as f32 - width - TEXT_SCALE * 8.0;
+        overlay.rect(
+            left,
+            TEXT_SCALE * 2.0,
+            width + TEXT_SCALE * 8.0,
+            height + TEXT_SCALE * 4.0,
+            BACKGROUND,
+        );
+        overlay.text(
+            left + TEXT_SCALE * 4.0,
+            TEXT_SCALE * 4.0,
+            TEXT_SCALE,
+            &text,
+            TEXT,
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+use ffmpeg_next::{format::Pixel, util::frame::Video, Rational};
+
+use crate::source::FrameSource;
+use crate::video::{StreamSelection, VideoStream};
+
+/// Extensions of files read as images rather than videos.
+const EXTENSIONS: &[&str] = &[
+    "png", "tif", "tiff", "exr", "dpx", "jpg", "jpeg", "bmp", "webp",
+];
+
+pub fn is_image(path: &Path) -> bool {
+    matches!(path.extension(), Some(extension) if EXTENSIONS
+        .iter()
+        .any(|image| extension.eq_ignore_ascii_case(image)))
+}
+
+/// Numbered image files played as a video, or a single image shown for as
+/// long as the videos it is compared against. Every file is decoded with
+/// ffmpeg, so any image format it reads works, and seeking is exact.
+pub struct ImageSequence {
+    paths: Vec<PathBuf>,
+    frame_rate: Rational,
+    /// The image when there is only one and it repeats forever.
+    still: Option<Video>,
+    dimensions: (u32, u32),
+    format: Pixel,
+    frame_index: Option<usize>,
+}
+
+impl ImageSequence {
+    /// All images in `directory` in the order of their names.
+    pub fn directory(directory: &Path, frame_rate: Rational) -> Self {
+        let mut paths = std::fs::read_dir(directory)
+            .expect("to read the directory")
+            .map(|entry| entry.expect("directory entry").path())
+            .filter(|path| is_image(path))
+            .collect::<Vec<_>>();
+        paths.sort();
+        Self::new(paths, frame_rate, false)
+    }
+
+    /// Images numbered as in the printf style `pattern`, e.g.
+    /// `frames/%05d.png`, in the order of their numbers.
+    pub fn pattern(pattern: &str, frame_rate: Rational) -> Self {
+        let pattern = Path::new(pattern);
+        let directory = pattern
+            .parent()
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let name = pattern
+            .file_name()
+            .expect("a file name pattern")
+            .to_string_lossy();
+        let (prefix, rest) = name.split_once('%').expect("a % in the pattern");
+        let (width, suffix) = rest.split_once('d').expect("a %d in the pattern");
+        let width = width.parse::<usize>().unwrap_or(0);
+
+        let mut numbered = std::fs::read_dir(directory)
+            .expect("to read the directory")
+            .filter_map(|entry| {
+                let path = entry.expect("directory entry").path();
+                let file_name = path.file_name()?.to_str()?;
+                let digits = file_name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                if digits.len() < width || !digits.chars().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                Some((digits.parse::<u64>().ok()?, path))
+            })
+            .collect::<Vec<_>>();
+        numbered.sort();
+        Self::new(
+            numbered.into_iter().map(|(_, path)| path).collect(),
+            frame_rate,
+            false,
+        )
+    }
+
+    /// A single image, repeated for every frame.
+    pub fn still(path: &Path, frame_rate: Rational) -> Self {
+        Self::new(vec![path.to_path_buf()], frame_rate, true)
+    }
+
+    fn new(paths: Vec<PathBuf>, frame_rate: Rational, still: bool) -> Self {
+        let first = decode(paths.first().expect("at least one image"));
+        Self {
+            dimensions: (first.width(), first.height()),
+            format: first.format(),
+            still: if still { Some(first) } else { None },
+            paths,
+            frame_rate,
+            frame_index: None,
+        }
+    }
+
+    fn read(&mut self, index: usize) -> Option<Video> {
+        let frame = match &self.still {
+            Some(still) => still.clone(),
+            None => decode(self.paths.get(index)?),
+        };
+        self.frame_index = Some(index);
+        Some(frame)
+    }
+}
+
+impl FrameSource for ImageSequence {
+    fn next_frame(&mut self) -> Option<Video> {
+        let index = self.frame_index.map_or(0, |index| index + 1);
+        self.read(index)
+    }
+
+    fn seek(&mut self, index: usize) -> Option<Video> {
+        self.read(index)
+    }
+
+    fn frame_index(&self) -> usize {
+        self.frame_index.unwrap_or(0)
+    }
+
+    fn frame_rate(&self) -> Rational {
+        self.frame_rate
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    fn format(&self) -> Pixel {
+        self.format
+    }
+
+    fn is_still(&self) -> bool {
+        self.still.is_some()
+    }
+}
+
+fn decode(path: &Path) -> Video {
+    VideoStream::decoded(&path.to_string_lossy(), &StreamSelection::Best)
+        .get_next_decoded_frame()
+        .expect("to decode the image")
+}
@@ -0,0 +1,349 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::mem::size_of;
+
+use ffmpeg_next::{
+    codec, color, decoder, format::context::Input, format::stream::Stream, format::Pixel,
+    frame::side_data, media, picture, util::frame::Video, Rational,
+};
+
+use crate::font;
+use crate::overlay::{Color, Overlay};
+
+const TEXT_SCALE: f32 = 1.0;
+const MARGIN: f32 = 8.0;
+const BACKGROUND: Color = [0.0, 0.0, 0.0, 0.7];
+const TEXT: Color = [1.0, 1.0, 1.0, 1.0];
+
+/// A stream of an input file, see [`crate::source::FrameSource::streams`].
+#[derive(Clone, Debug)]
+pub struct StreamInfo {
+    pub index: usize,
+    pub medium: media::Type,
+    pub codec: &'static str,
+    pub language: Option<String>,
+    /// Whether it is the video stream decoded.
+    pub selected: bool,
+}
+
+impl StreamInfo {
+    pub(crate) fn new(stream: &Stream, selected: bool) -> Self {
+        let parameters = stream.parameters();
+        Self {
+            index: stream.index(),
+            medium: parameters.medium(),
+            codec: parameters.id().name(),
+            language: stream.metadata().get("language").map(str::to_string),
+            selected,
+        }
+    }
+}
+
+impl fmt::Display for StreamInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let medium = match self.medium {
+            media::Type::Video => "video",
+            media::Type::Audio => "audio",
+            media::Type::Subtitle => "subtitle",
+            media::Type::Data => "data",
+            media::Type::Attachment => "attachment",
+            _ => "unknown",
+        };
+        write!(f, "#{} {} {}", self.index, medium, self.codec)?;
+        if let Some(language) = &self.language {
+            write!(f, " {}", language)?;
+        }
+        if self.selected {
+            write!(f, " (shown)")?;
+        }
+        Ok(())
+    }
+}
+
+/// What ffprobe tells about the video stream decoded, see
+/// [`crate::source::FrameSource::media_info`].
+#[derive(Clone, Debug)]
+pub struct MediaInfo {
+    /// Name of the container format such as `matroska,webm`.
+    pub container: String,
+    pub codec: &'static str,
+    pub profile: Option<String>,
+    /// Level the way the codec writes it, e.g. 4.1.
+    pub level: Option<String>,
+    pub dimensions: (u32, u32),
+    /// Sample aspect ratio, 0:1 if unknown.
+    pub sample_aspect_ratio: Rational,
+    pub pixel_format: Pixel,
+    pub color_primaries: color::Primaries,
+    pub color_transfer: color::TransferCharacteristic,
+    pub color_matrix: color::Space,
+    pub color_range: color::Range,
+    /// Bits per second of the stream, or of the whole file if the stream
+    /// doesn't say.
+    pub bit_rate: Option<u64>,
+    pub frame_rate: Rational,
+    /// Length in seconds.
+    pub duration: Option<f64>,
+}
+
+impl MediaInfo {
+    pub(crate) fn new(
+        ictx: &Input,
+        stream: &Stream,
+        decoder: &decoder::Video,
+        frame_rate: Rational,
+    ) -> Self {
+        // ffmpeg-next has no names for profiles and no level at all.
+        let (profile, level) = unsafe {
+            let context = &*decoder.as_ptr();
+            let name = ffmpeg_next::ffi::avcodec_profile_name(context.codec_id, context.profile);
+            let profile = Some(name)
+                .filter(|name| !name.is_null())
+                .map(|name| CStr::from_ptr(name).to_string_lossy().into_owned());
+            (profile, level(decoder.id(), context.level))
+        };
+        let bit_rate = Some(decoder.bit_rate() as u64)
+            .filter(|&bit_rate| bit_rate > 0)
+            .or_else(|| {
+                Some(ictx.bit_rate())
+                    .filter(|&bit_rate| bit_rate > 0)
+                    .map(|bit_rate| bit_rate as u64)
+            });
+        // AV_NOPTS_VALUE when unknown
+        let duration = Some(stream.duration())
+            .filter(|&duration| duration > 0)
+            .map(|duration| duration as f64 * f64::from(stream.time_base()))
+            .or_else(|| {
+                Some(ictx.duration())
+                    .filter(|&duration| duration > 0)
+                    .map(|duration| duration as f64 * f64::from(ffmpeg_next::rescale::TIME_BASE))
+            });
+
+        Self {
+            container: ictx.format().name().to_string(),
+            codec: decoder.id().name(),
+            profile,
+            level,
+            dimensions: (decoder.width(), decoder.height()),
+            sample_aspect_ratio: decoder.aspect_ratio(),
+            pixel_format: decoder.format(),
+            color_primaries: decoder.color_primaries(),
+            color_transfer: decoder.color_transfer_characteristic(),
+            color_matrix: decoder.color_space(),
+            color_range: decoder.color_range(),
+            bit_rate,
+            frame_rate,
+            duration,
+        }
+    }
+
+    /// Display aspect ratio, from the sample aspect ratio if known.
+    pub fn display_aspect_ratio(&self) -> Rational {
+        let (width, height) = (self.dimensions.0 as i64, self.dimensions.1 as i64);
+        let (width, height) = match self.sample_aspect_ratio {
+            sar if sar.numerator() > 0 && sar.denominator() > 0 => (
+                width * sar.numerator() as i64,
+                height * sar.denominator() as i64,
+            ),
+            _ => (width, height),
+        };
+        let divisor = gcd(width, height).max(1);
+        Rational::new((width / divisor) as i32, (height / divisor) as i32)
+    }
+
+    /// The panel of the video, a line for each of container, codec, picture
+    /// and colour.
+    pub fn lines(&self) -> Vec<String> {
+        let duration = match self.duration {
+            Some(duration) => format!(
+                "{}:{:02}:{:06.3}",
+                (duration / 3600.0) as u64,
+                (duration / 60.0) as u64 % 60,
+                duration % 60.0
+            ),
+            None => "unknown".to_string(),
+        };
+        let codec = [
+            Some(self.codec.to_string()),
+            self.profile.clone(),
+            self.level.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+        let bit_rate = match self.bit_rate {
+            Some(bit_rate) => format!("{:.0} kb/s", bit_rate as f64 / 1000.0),
+            None => "unknown bit rate".to_string(),
+        };
+        let sample_aspect_ratio = match self.sample_aspect_ratio {
+            sar if sar.numerator() > 0 => format!("{}:{}", sar.numerator(), sar.denominator()),
+            _ => "unknown".to_string(),
+        };
+        let display_aspect_ratio = self.display_aspect_ratio();
+        let pixel_format = match self.pixel_format.descriptor() {
+            Some(descriptor) => descriptor.name().to_string(),
+            None => format!("{:?}", self.pixel_format),
+        };
+        let range = match self.color_range {
+            color::Range::MPEG => "limited",
+            color::Range::JPEG => "full",
+            _ => "unknown",
+        };
+
+        vec![
+            format!("{}  duration {}", self.container, duration),
+            format!(
+                "{}  {}  {:.3} fps",
+                codec,
+                bit_rate,
+                f64::from(self.frame_rate)
+            ),
+            format!(
+                "{}x{}  SAR {}  DAR {}:{}  {}",
+                self.dimensions.0,
+                self.dimensions.1,
+                sample_aspect_ratio,
+                display_aspect_ratio.numerator(),
+                display_aspect_ratio.denominator(),
+                pixel_format
+            ),
+            format!(
+                "primaries {:?}  transfer {:?}  matrix {:?}  {} range",
+                self.color_primaries, self.color_transfer, self.color_matrix, range
+            ),
+        ]
+    }
+}
+
+/// `level` of a stream of codec `id` as it is usually written, `None` if
+/// unknown.
+fn level(id: codec::Id, level: i32) -> Option<String> {
+    // FF_LEVEL_UNKNOWN
+    if level < 0 {
+        return None;
+    }
+    Some(match id {
+        // level_idc, where 9 is level 1b
+        codec::Id::H264 if level == 9 => "1b".to_string(),
+        codec::Id::H264 => format!("{}.{}", level / 10, level % 10),
+        // general_level_idc is 30 times the level
+        codec::Id::HEVC => format!("{}.{}", level / 30, level % 30 / 3),
+        _ => level.to_string(),
+    })
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Add a panel with `lines` about a video to `overlay`, in the top left
+/// corner of `placement`, its left, top, width and height in pixels.
+pub fn draw(overlay: &mut Overlay, placement: (f32, f32, f32, f32), lines: &[String]) {
+    let (left, top) = (placement.0.max(0.0) + MARGIN, placement.1.max(0.0) + MARGIN);
+    let line_height = (font::GLYPH_SIZE.1 + 4) as f32 * TEXT_SCALE;
+    let width = lines
+        .iter()
+        .map(|line| Overlay::text_size(line, TEXT_SCALE).0)
+        .fold(0.0, f32::max);
+    overlay.rect(
+        left,
+        top,
+        width + 2.0 * MARGIN,
+        lines.len() as f32 * line_height + 2.0 * MARGIN,
+        BACKGROUND,
+    );
+    for (number, line) in lines.iter().enumerate() {
+        overlay.text(
+            left + MARGIN,
+            top + MARGIN + number as f32 * line_height,
+            TEXT_SCALE,
+            line,
+            TEXT,
+        );
+    }
+}
+
+/// A line about the frame shown: its picture type and, from decoders that
+/// export them, its QPs.
+pub fn frame_line(frame: &Video) -> String {
+    let kind = match frame.kind() {
+        picture::Type::None => "unknown".to_string(),
+        kind => format!("{:?}", kind),
+    };
+    let mut line = format!("picture {}", kind);
+    if frame.is_key() {
+        line.push_str(" key");
+    }
+    let qp = frame
+        .side_data(side_data::Type::VIDEO_ENC_PARAMS)
+        .and_then(|side_data| qp(side_data.data()));
+    if let Some((base, mean)) = qp {
+        line.push_str(&format!("  QP {} mean {:.2}", base, mean));
+    }
+    line
+}
+
+/// AVVideoEncParams of libavutil/video_enc_params.h, which ffmpeg-sys-next
+/// has no bindings for, with the same layout.
+#[repr(C)]
+#[allow(dead_code)]
+struct AVVideoEncParams {
+    nb_blocks: u32,
+    blocks_offset: usize,
+    block_size: usize,
+    type_: i32,
+    qp: i32,
+    delta_qp: [[i32; 2]; 4],
+}
+
+/// AVVideoBlockParams of libavutil/video_enc_params.h, see
+/// [`AVVideoEncParams`].
+#[repr(C)]
+#[allow(dead_code)]
+struct AVVideoBlockParams {
+    src_x: i32,
+    src_y: i32,
+    w: i32,
+    h: i32,
+    delta_qp: i32,
+}
+
+/// Base QP of a frame and the mean over its blocks weighted by their area,
+/// from side data holding an AVVideoEncParams followed by its blocks.
+fn qp(data: &[u8]) -> Option<(i32, f64)> {
+    if data.len() < size_of::<AVVideoEncParams>() {
+        return None;
+    }
+    // Side data is only aligned for ffmpeg's own use of it.
+    let params = unsafe { std::ptr::read_unaligned(data.as_ptr() as *const AVVideoEncParams) };
+    // As av_video_enc_params_block does.
+    let block = |index: usize| {
+        let offset = params.blocks_offset + index * params.block_size;
+        if offset + size_of::<AVVideoBlockParams>() > data.len() {
+            return None;
+        }
+        Some(unsafe {
+            std::ptr::read_unaligned(data[offset..].as_ptr() as *const AVVideoBlockParams)
+        })
+    };
+
+    let mut sum = 0.0;
+    let mut area = 0.0;
+    for index in 0..params.nb_blocks as usize {
+        let block = block(index)?;
+        let block_area = block.w as f64 * block.h as f64;
+        sum += (params.qp + block.delta_qp) as f64 * block_area;
+        area += block_area;
+    }
+    let mean = if area > 0.0 {
+        sum / area
+    } else {
+        params.qp as f64
+    };
+    Some((params.qp, mean))
+}
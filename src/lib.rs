@@ -1,32 +1,297 @@
-use clap::Parser;
+//! Side by side comparison of videos with quality metrics.
+//!
+//! Besides the `quick-compare` binary built around [`run`], the crate can be
+//! embedded: [`Renderer`] draws the comparison into any wgpu texture, with or
+//! without a window, [`VideoStream`] decodes videos with ffmpeg and
+//! [`metrics`] computes PSNR, SSIM and MS-SSIM of decoded frames.
+
+use clap::{Parser, Subcommand};
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 
+mod animation;
+pub mod audio;
+mod bitrate;
+mod browser;
+mod capture;
+mod compare;
+mod differences;
+mod font;
+mod images;
+mod info;
+pub mod live;
+pub mod loudness;
+pub mod metrics;
+mod overlay;
+mod pipe;
+pub mod raw;
+mod readout;
+mod remote;
+mod render;
+pub mod renderer;
+mod server;
+pub mod source;
 mod state;
+mod subtitles;
 mod texture;
-mod video;
+pub mod video;
+mod waveform;
+pub mod y4m;
+pub use ffmpeg_next;
+pub use renderer::{Layout, Renderer, RendererBuilder};
+pub use source::FrameSource;
 use state::State;
-use video::VideoStream;
+pub use video::VideoStream;
+pub use wgpu;
 
 /// Quick comparison of videos
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// list of videos to compare
+    /// list of videos to compare, each may pick a video stream with e.g.
+    /// file.mkv#v:1 for the second one, #v:eng by language or #3 by index
     videos: Vec<String>,
+    /// index of the video the live metrics are measured against
+    #[clap(long, default_value_t = 0)]
+    reference: usize,
+    /// scan the reference and the video after it for differences in the
+    /// background, to jump between them with N, P and W
+    #[clap(long)]
+    find_differences: bool,
+    /// luma PSNR in dB below which a frame counts as different
+    #[clap(long, default_value_t = 35.0)]
+    difference_threshold: f64,
+    /// number of worst frames W cycles through
+    #[clap(long, default_value_t = 10)]
+    worst: usize,
+    /// size of the screenshots taken with S as WIDTHxHEIGHT, defaults to the
+    /// window size
+    #[clap(long, value_parser = capture::parse_size)]
+    screenshot_size: Option<(u32, u32)>,
+    /// serve a page at this address, e.g. 0.0.0.0:8080, where browsers can
+    /// follow the comparison and control playback
+    #[clap(long, value_name = "ADDRESS")]
+    serve: Option<String>,
+    /// index of the video to hear, A switches between them. Only heard when
+    /// built with the audio feature
+    #[clap(long, value_name = "INDEX")]
+    audio: Option<usize>,
+    /// show the waveform, loudness, true peak and offset of the audio of
+    /// every video
+    #[clap(long)]
+    waveforms: bool,
+    /// show the bitrate of every video around the frame shown, with I, P and
+    /// B frames in their own colour
+    #[clap(long)]
+    bitrates: bool,
+    /// show the subtitles of every video, from a .srt, .ass, .ssa or .vtt
+    /// file with the same name or else from the video itself; text outside
+    /// ASCII shows as ?
+    #[clap(long)]
+    subtitles: bool,
+    /// how to arrange the videos
+    #[clap(long, value_enum, default_value = "side-by-side")]
+    layout: Layout,
+    #[clap(flatten)]
+    input: source::InputOptions,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compute PSNR, SSIM and MS-SSIM against the first video without opening a window
+    Compare {
+        /// reference video followed by the videos to compare against it
+        #[clap(required = true, min_values = 2)]
+        videos: Vec<String>,
+        /// format of the per-frame results
+        #[clap(long, value_enum, default_value = "csv")]
+        format: compare::OutputFormat,
+        /// file to write the results to instead of stdout
+        #[clap(short, long)]
+        output: Option<String>,
+        #[clap(flatten)]
+        thresholds: compare::Thresholds,
+        #[clap(flatten)]
+        input: source::InputOptions,
+    },
+    /// Render the comparison to a video file without showing it
+    Render {
+        /// list of videos to compare
+        #[clap(required = true)]
+        videos: Vec<String>,
+        /// video file to write, the container is guessed from the extension;
+        /// .y4m files and - for stdout get uncompressed YUV4MPEG2
+        #[clap(short, long)]
+        output: String,
+        /// index of the video the metrics are measured against
+        #[clap(long, default_value_t = 0)]
+        reference: usize,
+        /// first frame to render
+        #[clap(long, default_value_t = 0)]
+        start: usize,
+        /// number of frames to render, defaults to until the first video ends
+        #[clap(long)]
+        frames: Option<usize>,
+        /// size of the rendered video as WIDTHxHEIGHT
+        #[clap(long, value_parser = capture::parse_size, default_value = "1920x1080")]
+        output_size: (u32, u32),
+        /// how to arrange the videos
+        #[clap(long, value_enum, default_value = "side-by-side")]
+        layout: Layout,
+        /// render in software even when there is a GPU
+        #[clap(long)]
+        fallback_adapter: bool,
+        #[clap(flatten)]
+        input: source::InputOptions,
+    },
+    /// Export a short loop as an animated GIF or APNG with the labels burned in
+    Animation {
+        /// list of videos to compare
+        #[clap(required = true)]
+        videos: Vec<String>,
+        /// file to write, a GIF for the .gif extension and an APNG otherwise
+        #[clap(short, long)]
+        output: String,
+        /// index of the video the metrics are measured against
+        #[clap(long, default_value_t = 0)]
+        reference: usize,
+        /// first frame of the loop, or the frame to flicker at
+        #[clap(long, default_value_t = 0)]
+        start: usize,
+        /// number of frames in the loop
+        #[clap(long, default_value_t = 30)]
+        frames: usize,
+        /// alternate between the videos with indices A and B at the start frame
+        /// instead of playing a range
+        #[clap(long, number_of_values = 2, value_names = &["A", "B"])]
+        flicker: Option<Vec<usize>>,
+        /// size of the animation as WIDTHxHEIGHT
+        #[clap(long, value_parser = capture::parse_size, default_value = "960x540")]
+        output_size: (u32, u32),
+        /// milliseconds every image is shown, defaults to the frame rate of the
+        /// reference or half a second when flickering
+        #[clap(long)]
+        delay: Option<u32>,
+        /// how to arrange the videos
+        #[clap(long, value_enum, default_value = "side-by-side")]
+        layout: Layout,
+        /// render in software even when there is a GPU
+        #[clap(long)]
+        fallback_adapter: bool,
+        #[clap(flatten)]
+        input: source::InputOptions,
+    },
 }
 
+/// Run the command line interface, parsing the arguments of the process.
 pub async fn run() {
     let args = Args::parse();
+    match args.command {
+        Some(Command::Compare {
+            videos,
+            format,
+            output,
+            thresholds,
+            input,
+        }) => {
+            if !compare::run(&videos, format, output.as_deref(), &thresholds, &input) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::Render {
+            videos,
+            output,
+            reference,
+            start,
+            frames,
+            output_size: size,
+            layout,
+            fallback_adapter,
+            input,
+        }) => {
+            let mut renderer = Renderer::builder()
+                .videos(videos)
+                .reference(reference)
+                .size(size.0, size.1)
+                .layout(layout)
+                .force_fallback_adapter(fallback_adapter)
+                .input_options(input)
+                .build_headless()
+                .await;
+            render::run(&mut renderer, &output, start, frames, size);
+            return;
+        }
+        Some(Command::Animation {
+            videos,
+            output,
+            reference,
+            start,
+            frames,
+            flicker,
+            output_size: size,
+            delay,
+            layout,
+            fallback_adapter,
+            input,
+        }) => {
+            let mut renderer = Renderer::builder()
+                .videos(videos)
+                .reference(reference)
+                .size(size.0, size.1)
+                .layout(layout)
+                .force_fallback_adapter(fallback_adapter)
+                .input_options(input)
+                .build_headless()
+                .await;
+            let flicker = flicker.map(|flicker| (flicker[0], flicker[1]));
+            if let Err(error) =
+                animation::run(&mut renderer, &output, start, frames, flicker, size, delay)
+            {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => {}
+    }
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title("quick compare")
         .build(&event_loop)
         .unwrap();
-    let mut state = State::new(&window, args.videos).await;
+    let mut state = State::new(&window, args.videos, args.reference, args.audio, args.input).await;
+    state.renderer.set_layout(args.layout);
+    if args.find_differences {
+        state
+            .renderer
+            .find_differences(args.difference_threshold, args.worst);
+    }
+    if args.waveforms {
+        state.renderer.show_waveforms();
+    }
+    if args.bitrates {
+        state.renderer.show_bitrates();
+    }
+    if args.subtitles {
+        state.renderer.show_subtitles();
+    }
+    let mut remote =
+        args.serve
+            .as_deref()
+            .and_then(|address| match remote::RemoteViewer::serve(address) {
+                Ok(remote) => Some(remote),
+                Err(error) => {
+                    eprintln!("can't serve the remote viewer at {}: {}", address, error);
+                    None
+                }
+            });
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             ref event,
@@ -61,9 +326,9 @@ pub async fn run() {
                 ..
             } => {
                 if state.video_status == state::VideoStatus::Paused {
-                    state.get_next_frame();
+                    state.renderer.get_next_frame();
                 }
-                state.update();
+                state.renderer.update();
             }
             WindowEvent::KeyboardInput {
                 input:
@@ -75,9 +340,130 @@ pub async fn run() {
                 ..
             } => {
                 if state.video_status == state::VideoStatus::Paused {
-                    state.get_previous_frame();
+                    state.renderer.get_previous_frame();
                 }
-                state.update();
+                state.renderer.update();
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::R),
+                        ..
+                    },
+                ..
+            } => state.renderer.next_reference(),
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::M),
+                        ..
+                    },
+                ..
+            } => state.renderer.toggle_graph_metric(),
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::A),
+                        ..
+                    },
+                ..
+            } => state.next_audio(),
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::I),
+                        ..
+                    },
+                ..
+            } => state.renderer.toggle_info(),
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::V),
+                        ..
+                    },
+                ..
+            } => {
+                state.next_stream();
+                state.renderer.update();
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::N),
+                        ..
+                    },
+                ..
+            } => {
+                state.next_difference();
+                state.renderer.update();
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::P),
+                        ..
+                    },
+                ..
+            } => {
+                state.previous_difference();
+                state.renderer.update();
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::W),
+                        ..
+                    },
+                ..
+            } => {
+                state.next_worst_difference();
+                state.renderer.update();
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::S),
+                        ..
+                    },
+                ..
+            } => match state.renderer.screenshot(args.screenshot_size) {
+                Ok(path) => println!("saved screenshot to {}", path.display()),
+                Err(error) => eprintln!("can't save the screenshot: {}", error),
+            },
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::F),
+                        ..
+                    },
+                ..
+            } => {
+                for exported in state.renderer.export_frames() {
+                    match exported {
+                        Ok(path) => println!("exported frame to {}", path.display()),
+                        Err(error) => eprintln!("can't export the frame: {}", error),
+                    }
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => state.cursor_moved(*position),
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                state.click();
+                state.renderer.update();
             }
             WindowEvent::Resized(physical_size) => {
                 state.resize(*physical_size);
@@ -88,10 +474,14 @@ pub async fn run() {
             _ => {}
         },
         Event::RedrawRequested(window_id) if window_id == window.id() => {
+            if let Some(remote) = &remote {
+                remote.controls().for_each(|control| state.control(control));
+            }
             if state.video_status == state::VideoStatus::Playing {
-                state.get_next_frame();
+                state.renderer.get_next_frame();
             }
-            state.update();
+            state.renderer.update();
+            state.sync_audio();
             match state.render() {
                 Ok(_) => {}
                 // Reconfigure the surface if lost
@@ -101,6 +491,11 @@ pub async fn run() {
                 // All other errors (Outdated, Timeout) should be resolved by the next frame
                 Err(e) => eprintln!("{:?}", e),
             }
+            if let Some(remote) = &mut remote {
+                if remote.wants_frame() {
+                    remote.send(state.renderer.capture(remote::SIZE), remote::SIZE);
+                }
+            }
         }
         Event::MainEventsCleared => window.request_redraw(),
         _ => {}
@@ -0,0 +1,111 @@
+use std::time::{Duration, Instant};
+
+use ffmpeg_next::Dictionary;
+
+/// Protocols of inputs that are received as they are sent rather than read
+/// from storage.
+const SCHEMES: &[&str] = &["rtsp", "rtsps", "rtmp", "rtmps", "srt", "udp", "rtp", "tcp"];
+/// Microseconds without data before reading a live input fails.
+const TIMEOUT: &str = "5000000";
+/// Attempts to reopen a live input after it stopped.
+pub const RECONNECTS: usize = 5;
+/// Pause before reopening a live input.
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// Packets of a live input read ahead of decoding, a few seconds of video.
+pub const QUEUED_PACKETS: usize = 120;
+
+/// Whether `path` is the URL of a live stream.
+pub fn is_live(path: &str) -> bool {
+    matches!(path.split_once("://"), Some((scheme, _))
+        if SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()))
+}
+
+/// ffmpeg options to open the live stream at `url` with little buffering,
+/// giving up on it when nothing arrives for a while.
+pub fn options(url: &str) -> Dictionary<'static> {
+    let mut options = Dictionary::new();
+    options.set("fflags", "nobuffer+discardcorrupt");
+    options.set("flags", "low_delay");
+    options.set("analyzeduration", "1000000");
+    options.set("rw_timeout", TIMEOUT);
+    let scheme = url
+        .split_once("://")
+        .map(|(scheme, _)| scheme.to_ascii_lowercase())
+        .unwrap_or_default();
+    match scheme.as_str() {
+        "rtsp" | "rtsps" => {
+            options.set("rtsp_transport", "tcp");
+            options.set("timeout", TIMEOUT);
+        }
+        "rtmp" | "rtmps" => options.set("rtmp_live", "live"),
+        "srt" => options.set("mode", "caller"),
+        "udp" | "rtp" => {
+            options.set("timeout", TIMEOUT);
+            options.set("overrun_nonfatal", "1");
+            options.set("fifo_size", "1000000");
+        }
+        _ => {}
+    }
+    options
+}
+
+/// How a live input is keeping up.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LiveStatus {
+    /// How far the frame last decoded is behind the first one, compared to
+    /// the time since the first one arrived. Grows when frames are buffered
+    /// faster than they are shown.
+    pub latency: Duration,
+    /// Packets the decoder rejected as lost or corrupt or skipped to catch up
+    /// with the stream. Browser cameras count the frames skipped for newer
+    /// ones and the frames shown again when no new one arrived in time.
+    pub dropped: usize,
+    pub reconnects: usize,
+}
+
+impl std::fmt::Display for LiveStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "latency {} ms", self.latency.as_millis())?;
+        if self.dropped > 0 {
+            write!(f, "  dropped {}", self.dropped)?;
+        }
+        if self.reconnects > 0 {
+            write!(f, "  reconnects {}", self.reconnects)?;
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the [`LiveStatus`] of a live input.
+pub(crate) struct Live {
+    /// When the first frame since connecting arrived, with its timestamp in
+    /// seconds.
+    start: Option<(Instant, f64)>,
+    pub status: LiveStatus,
+}
+
+impl Live {
+    pub fn new() -> Self {
+        Self {
+            start: None,
+            status: LiveStatus::default(),
+        }
+    }
+
+    /// Note that the frame at `timestamp` seconds was just decoded.
+    pub fn frame(&mut self, timestamp: f64) {
+        match self.start {
+            Some((arrived, first)) => {
+                let behind = arrived.elapsed().as_secs_f64() - (timestamp - first);
+                self.status.latency = Duration::from_secs_f64(behind.max(0.0));
+            }
+            None => self.start = Some((Instant::now(), timestamp)),
+        }
+    }
+
+    /// Count a reconnect, after which timestamps start over.
+    pub fn reconnect(&mut self) {
+        self.status.reconnects += 1;
+        self.start = None;
+    }
+}
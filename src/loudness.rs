@@ -0,0 +1,323 @@
+//! Loudness and true peak after EBU R128 / ITU-R BS.1770, and the offset
+//! between two recordings of the same audio.
+
+use std::f64::consts::PI;
+
+/// Length of a gating block and the step between blocks in seconds.
+const BLOCK: f64 = 0.4;
+const STEP: f64 = 0.1;
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Relative gate in LU below the loudness of the blocks above the absolute gate.
+const RELATIVE_GATE: f64 = -10.0;
+/// Times the signal is oversampled to find the true peak.
+const OVERSAMPLING: usize = 4;
+/// Taps of every phase of the interpolation filter.
+const TAPS: usize = 12;
+
+/// Second order IIR filter in direct form I.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn filter(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// The K-weighting filter of BS.1770: a high shelf modelling the head
+/// followed by a high pass, for any sample rate.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// Weight of channel `index` out of `channels` in the default ffmpeg order,
+/// where 5.1 is L R C LFE Ls Rs.
+fn channel_weight(index: usize, channels: usize) -> f64 {
+    if channels < 6 {
+        return 1.0;
+    }
+    match index {
+        3 => 0.0,
+        4 | 5 => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Loudness of a mean square weighted power.
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Windowed sinc interpolation filter for every phase of the oversampling.
+fn interpolation_filter() -> [[f64; TAPS]; OVERSAMPLING] {
+    let mut filter = [[0.0; TAPS]; OVERSAMPLING];
+    let length = (TAPS * OVERSAMPLING) as f64;
+    for (phase, taps) in filter.iter_mut().enumerate() {
+        for (tap, coefficient) in taps.iter_mut().enumerate() {
+            let n = (tap * OVERSAMPLING + phase) as f64;
+            let t = (n - length / 2.0) / OVERSAMPLING as f64;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (PI * t).sin() / (PI * t)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * n / length).cos();
+            *coefficient = sinc * window;
+        }
+    }
+    filter
+}
+
+/// Measures integrated loudness and true peak of interleaved samples fed to
+/// it in pieces of any size.
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<[Biquad; 2]>,
+    /// Samples per channel in a step of the gating blocks.
+    step_length: usize,
+    /// Weighted square sum of the step being measured and its length so far.
+    step_sum: f64,
+    step_samples: usize,
+    /// Mean weighted power of every complete step.
+    steps: Vec<f64>,
+    interpolation: [[f64; TAPS]; OVERSAMPLING],
+    /// Last samples of every channel for the interpolation.
+    history: Vec<[f64; TAPS]>,
+    peak: f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            channels,
+            filters: vec![k_weighting(sample_rate); channels],
+            step_length: (sample_rate as f64 * STEP) as usize,
+            step_sum: 0.0,
+            step_samples: 0,
+            steps: Vec::new(),
+            interpolation: interpolation_filter(),
+            history: vec![[0.0; TAPS]; channels],
+            peak: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                let sample = sample as f64;
+                let [shelf, high_pass] = &mut self.filters[channel];
+                let weighted = high_pass.filter(shelf.filter(sample));
+                self.step_sum += channel_weight(channel, self.channels) * weighted * weighted;
+                self.true_peak_sample(channel, sample);
+            }
+            self.step_samples += 1;
+            if self.step_samples == self.step_length {
+                self.steps.push(self.step_sum / self.step_length as f64);
+                self.step_sum = 0.0;
+                self.step_samples = 0;
+            }
+        }
+    }
+
+    fn true_peak_sample(&mut self, channel: usize, sample: f64) {
+        let history = &mut self.history[channel];
+        history.copy_within(1.., 0);
+        history[TAPS - 1] = sample;
+        for taps in &self.interpolation {
+            let interpolated: f64 = taps.iter().zip(history.iter()).map(|(t, x)| t * x).sum();
+            self.peak = self.peak.max(interpolated.abs());
+        }
+        self.peak = self.peak.max(sample.abs());
+    }
+
+    /// Integrated loudness in LUFS of everything added so far, `None` for
+    /// less than a block or silence.
+    pub fn integrated(&self) -> Option<f64> {
+        let steps_per_block = (BLOCK / STEP).round() as usize;
+        let blocks = self
+            .steps
+            .windows(steps_per_block)
+            .map(|steps| steps.iter().sum::<f64>() / steps_per_block as f64)
+            .filter(|&power| power > 0.0 && lufs(power) > ABSOLUTE_GATE)
+            .collect::<Vec<_>>();
+        if blocks.is_empty() {
+            return None;
+        }
+        let mean = |blocks: &mut dyn Iterator<Item = f64>| {
+            let (sum, count) =
+                blocks.fold((0.0, 0), |(sum, count), power| (sum + power, count + 1));
+            sum / count.max(1) as f64
+        };
+
+        let gate = lufs(mean(&mut blocks.iter().copied())) + RELATIVE_GATE;
+        let gated = mean(&mut blocks.iter().copied().filter(|&power| lufs(power) > gate));
+        Some(lufs(gated))
+    }
+
+    /// True peak in dBTP of everything added so far.
+    pub fn true_peak(&self) -> f64 {
+        20.0 * self.peak.log10()
+    }
+}
+
+/// Time in seconds by which `other` lags behind `reference`, negative when
+/// it is ahead, from the cross-correlation of their envelopes sampled at
+/// `rate` per second. Offsets up to `max_offset` seconds are considered.
+pub fn offset(reference: &[f32], other: &[f32], rate: f64, max_offset: f64) -> Option<f64> {
+    let normalize = |envelope: &[f32]| {
+        let mean = envelope.iter().map(|&value| value as f64).sum::<f64>() / envelope.len() as f64;
+        envelope
+            .iter()
+            .map(|&value| value as f64 - mean)
+            .collect::<Vec<_>>()
+    };
+    if reference.is_empty() || other.is_empty() {
+        return None;
+    }
+    let (reference, other) = (normalize(reference), normalize(other));
+    let max_lag = (max_offset * rate) as isize;
+
+    let correlation = |lag: isize| {
+        let mut sum = 0.0;
+        let mut count = 0;
+        for (t, value) in reference.iter().enumerate() {
+            let shifted = t as isize + lag;
+            if shifted >= 0 && (shifted as usize) < other.len() {
+                sum += value * other[shifted as usize];
+                count += 1;
+            }
+        }
+        // Short overlaps at large lags correlate by chance.
+        if count < reference.len().min(other.len()) / 2 {
+            return None;
+        }
+        Some(sum / count as f64)
+    };
+    let scores = (-max_lag..=max_lag)
+        .map(|lag| (lag, correlation(lag)))
+        .filter_map(|(lag, score)| Some((lag, score?)))
+        .collect::<Vec<_>>();
+    let best = (0..scores.len()).max_by(|&a, &b| scores[a].1.total_cmp(&scores[b].1))?;
+    if scores[best].1 <= 0.0 {
+        return None;
+    }
+
+    // Between envelope samples, at the top of a parabola through the best
+    // score and its neighbours.
+    let mut lag = scores[best].0 as f64;
+    if best > 0 && best + 1 < scores.len() {
+        let (before, at, after) = (scores[best - 1].1, scores[best].1, scores[best + 1].1);
+        let curvature = before - 2.0 * at + after;
+        if curvature < 0.0 {
+            lag += 0.5 * (before - after) / curvature;
+        }
+    }
+    Some(lag / rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_at_minus_20_dbfs() {
+        let rate = 48000;
+        let amplitude = 10f64.powf(-20.0 / 20.0);
+        let samples = (0..rate * 5)
+            .map(|t| {
+                let phase = 2.0 * std::f64::consts::PI * 997.0 * t as f64 / rate as f64;
+                (amplitude * phase.sin()) as f32
+            })
+            .collect::<Vec<_>>();
+        let mut meter = LoudnessMeter::new(rate as u32, 1);
+        // In uneven pieces, as decoded audio comes.
+        for piece in samples.chunks(1000) {
+            meter.add(piece);
+        }
+        let integrated = meter.integrated().unwrap();
+        assert!((integrated + 23.0).abs() < 0.1, "{} LUFS", integrated);
+        assert!((meter.true_peak() + 20.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let mut meter = LoudnessMeter::new(48000, 2);
+        meter.add(&[0.0; 48000 * 2]);
+        assert_eq!(meter.integrated(), None);
+    }
+
+    /// Noise, which correlates only with itself at the right lag.
+    fn envelope(length: usize) -> Vec<f32> {
+        let mut state = 0x2545_f491_u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as f32 / u32::MAX as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn offset_of_shifted_envelope() {
+        let rate = 100.0;
+        let reference = envelope(2000);
+        // `other` lags 0.37 s behind.
+        let mut other = vec![0.5; 37];
+        other.extend_from_slice(&reference[..reference.len() - 37]);
+        let lag = offset(&reference, &other, rate, 2.0).unwrap();
+        assert!((lag - 0.37).abs() < 0.005, "{} s", lag);
+        let lead = offset(&other, &reference, rate, 2.0).unwrap();
+        assert!((lead + 0.37).abs() < 0.005, "{} s", lead);
+    }
+
+    #[test]
+    fn no_offset_of_empty_envelope() {
+        assert_eq!(offset(&[], &envelope(10), 100.0, 1.0), None);
+    }
+}
@@ -0,0 +1,368 @@
+use ffmpeg_next::util::frame::Video;
+
+/// PSNR reported for identical planes, where the mean squared error is zero.
+pub const MAX_PSNR: f64 = 100.0;
+
+const SSIM_WINDOW: usize = 8;
+const SSIM_STEP: usize = 4;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+/// Exponents of each scale from Wang et al., "Multi-scale structural
+/// similarity for image quality assessment".
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// A single 8 bit plane of a frame.
+#[derive(Clone, Copy)]
+pub struct Plane<'a> {
+    pub data: &'a [u8],
+    pub stride: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl<'a> Plane<'a> {
+    pub fn from_frame(frame: &'a Video, index: usize) -> Self {
+        Self {
+            data: frame.data(index),
+            stride: frame.stride(index),
+            width: frame.plane_width(index) as usize,
+            height: frame.plane_height(index) as usize,
+        }
+    }
+
+    fn row(&self, y: usize) -> &'a [u8] {
+        &self.data[y * self.stride..y * self.stride + self.width]
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, serde::Serialize)]
+pub struct Metrics {
+    pub psnr: Psnr,
+    pub ssim: f64,
+    pub ms_ssim: f64,
+}
+
+impl Metrics {
+    /// Column names matching the order of [`Metrics::values`].
+    pub const NAMES: [&'static str; 6] = [
+        "psnr_y",
+        "psnr_u",
+        "psnr_v",
+        "psnr_weighted",
+        "ssim",
+        "ms_ssim",
+    ];
+
+    /// All metrics of two YUV frames with the same format and size. SSIM and
+    /// MS-SSIM are computed on luma only.
+    pub fn from_frames(reference: &Video, distorted: &Video) -> Self {
+        let reference_luma = Plane::from_frame(reference, 0);
+        let distorted_luma = Plane::from_frame(distorted, 0);
+
+        Self {
+            psnr: Psnr::from_frames(reference, distorted),
+            ssim: ssim(reference_luma, distorted_luma),
+            ms_ssim: ms_ssim(reference_luma, distorted_luma),
+        }
+    }
+
+    pub fn values(&self) -> [f64; 6] {
+        [
+            self.psnr.y,
+            self.psnr.u,
+            self.psnr.v,
+            self.psnr.weighted,
+            self.ssim,
+            self.ms_ssim,
+        ]
+    }
+
+    fn from_values([y, u, v, weighted, ssim, ms_ssim]: [f64; 6]) -> Self {
+        Self {
+            psnr: Psnr { y, u, v, weighted },
+            ssim,
+            ms_ssim,
+        }
+    }
+}
+
+/// Statistics over all frames, computed separately for every metric.
+#[derive(Clone, Copy, Debug, Default, serde::Serialize)]
+pub struct Summary {
+    pub mean: Metrics,
+    pub min: Metrics,
+    pub p1: Metrics,
+    pub p5: Metrics,
+    pub p50: Metrics,
+}
+
+impl Summary {
+    pub fn from_frames(frames: &[Metrics]) -> Self {
+        if frames.is_empty() {
+            return Self::default();
+        }
+
+        let mut columns = [(); 6].map(|_| Vec::with_capacity(frames.len()));
+        for frame in frames {
+            for (column, value) in columns.iter_mut().zip(frame.values()) {
+                column.push(value);
+            }
+        }
+        columns
+            .iter_mut()
+            .for_each(|column| column.sort_by(|a, b| a.total_cmp(b)));
+
+        let percentile = |percentile: f64| {
+            Metrics::from_values(std::array::from_fn(|metric| {
+                let column = &columns[metric];
+                let index = (percentile / 100.0 * (column.len() - 1) as f64).round() as usize;
+                column[index]
+            }))
+        };
+
+        Self {
+            mean: Metrics::from_values(std::array::from_fn(|metric| {
+                columns[metric].iter().sum::<f64>() / frames.len() as f64
+            })),
+            min: percentile(0.0),
+            p1: percentile(1.0),
+            p5: percentile(5.0),
+            p50: percentile(50.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, serde::Serialize)]
+pub struct Psnr {
+    pub y: f64,
+    pub u: f64,
+    pub v: f64,
+    pub weighted: f64,
+}
+
+impl Psnr {
+    /// PSNR of every plane of two YUV frames with the same format and size.
+    pub fn from_frames(reference: &Video, distorted: &Video) -> Self {
+        let [y, u, v] = [0, 1, 2].map(|index| {
+            psnr(mse(
+                Plane::from_frame(reference, index),
+                Plane::from_frame(distorted, index),
+            ))
+        });
+
+        Self {
+            y,
+            u,
+            v,
+            weighted: (6.0 * y + u + v) / 8.0,
+        }
+    }
+}
+
+pub fn mse(reference: Plane, distorted: Plane) -> f64 {
+    let width = reference.width.min(distorted.width);
+    let height = reference.height.min(distorted.height);
+    if width == 0 || height == 0 {
+        return 0.0;
+    }
+
+    let sum: u64 = (0..height)
+        .map(|y| {
+            reference.row(y)[..width]
+                .iter()
+                .zip(&distorted.row(y)[..width])
+                .map(|(&a, &b)| {
+                    let diff = a as i64 - b as i64;
+                    (diff * diff) as u64
+                })
+                .sum::<u64>()
+        })
+        .sum();
+    sum as f64 / (width * height) as f64
+}
+
+pub fn psnr(mse: f64) -> f64 {
+    if mse <= 0.0 {
+        return MAX_PSNR;
+    }
+    (10.0 * (255.0 * 255.0 / mse).log10()).min(MAX_PSNR)
+}
+
+/// Mean SSIM over 8x8 windows spaced 4 pixels apart.
+pub fn ssim(reference: Plane, distorted: Plane) -> f64 {
+    ssim_components(&Luma::from_plane(reference), &Luma::from_plane(distorted)).0
+}
+
+/// Multi-scale SSIM over up to five scales, fewer if the plane gets smaller
+/// than a window before that.
+pub fn ms_ssim(reference: Plane, distorted: Plane) -> f64 {
+    let mut reference = Luma::from_plane(reference);
+    let mut distorted = Luma::from_plane(distorted);
+
+    let mut size = reference.width.min(reference.height);
+    let mut scales = 1;
+    while scales < MS_SSIM_WEIGHTS.len() && size / 2 >= SSIM_WINDOW {
+        size /= 2;
+        scales += 1;
+    }
+    let weights = &MS_SSIM_WEIGHTS[..scales];
+    let total: f64 = weights.iter().sum();
+
+    let mut result = 1.0;
+    for (scale, weight) in weights.iter().enumerate() {
+        let (ssim, cs) = ssim_components(&reference, &distorted);
+        if scale == scales - 1 {
+            result *= ssim.max(0.0).powf(weight / total);
+        } else {
+            result *= cs.max(0.0).powf(weight / total);
+            reference = reference.downsample();
+            distorted = distorted.downsample();
+        }
+    }
+    result
+}
+
+/// Mean SSIM and mean contrast-structure term over all windows.
+fn ssim_components(reference: &Luma, distorted: &Luma) -> (f64, f64) {
+    let width = reference.width.min(distorted.width);
+    let height = reference.height.min(distorted.height);
+    if width == 0 || height == 0 {
+        return (1.0, 1.0);
+    }
+    let window_width = SSIM_WINDOW.min(width);
+    let window_height = SSIM_WINDOW.min(height);
+    let count = (window_width * window_height) as f64;
+
+    let mut ssim_sum = 0.0;
+    let mut cs_sum = 0.0;
+    let mut windows = 0;
+    for top in (0..=height - window_height).step_by(SSIM_STEP) {
+        for left in (0..=width - window_width).step_by(SSIM_STEP) {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in top..top + window_height {
+                let row_a = &reference.data[y * reference.width + left..][..window_width];
+                let row_b = &distorted.data[y * distorted.width + left..][..window_width];
+                for (&a, &b) in row_a.iter().zip(row_b) {
+                    sum_a += a;
+                    sum_b += b;
+                    sum_aa += a * a;
+                    sum_bb += b * b;
+                    sum_ab += a * b;
+                }
+            }
+
+            let mean_a = sum_a / count;
+            let mean_b = sum_b / count;
+            let variance_a = sum_aa / count - mean_a * mean_a;
+            let variance_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+
+            let luminance =
+                (2.0 * mean_a * mean_b + SSIM_C1) / (mean_a * mean_a + mean_b * mean_b + SSIM_C1);
+            let cs = (2.0 * covariance + SSIM_C2) / (variance_a + variance_b + SSIM_C2);
+            ssim_sum += luminance * cs;
+            cs_sum += cs;
+            windows += 1;
+        }
+    }
+    (ssim_sum / windows as f64, cs_sum / windows as f64)
+}
+
+/// A plane converted to floating point so it can be downsampled between the
+/// scales of MS-SSIM.
+struct Luma {
+    data: Vec<f64>,
+    width: usize,
+    height: usize,
+}
+
+impl Luma {
+    fn from_plane(plane: Plane) -> Self {
+        Self {
+            data: (0..plane.height)
+                .flat_map(|y| plane.row(y).iter().map(|&value| value as f64))
+                .collect(),
+            width: plane.width,
+            height: plane.height,
+        }
+    }
+
+    /// Halve both dimensions by averaging 2x2 blocks.
+    fn downsample(&self) -> Self {
+        let width = self.width / 2;
+        let height = self.height / 2;
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let top = &self.data[2 * y * self.width..];
+            let bottom = &self.data[(2 * y + 1) * self.width..];
+            for x in 0..width {
+                data.push((top[2 * x] + top[2 * x + 1] + bottom[2 * x] + bottom[2 * x + 1]) / 4.0);
+            }
+        }
+        Self {
+            data,
+            width,
+            height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 64;
+    const HEIGHT: usize = 48;
+
+    /// A plane with a diagonal gradient, so windows aren't flat.
+    fn gradient(offset: u8) -> Vec<u8> {
+        (0..WIDTH * HEIGHT)
+            .map(|index| ((index % WIDTH + index / WIDTH) * 2) as u8 + offset)
+            .collect()
+    }
+
+    fn plane(data: &[u8]) -> Plane<'_> {
+        Plane {
+            data,
+            stride: WIDTH,
+            width: WIDTH,
+            height: HEIGHT,
+        }
+    }
+
+    #[test]
+    fn identical_planes() {
+        let data = gradient(0);
+        assert_eq!(mse(plane(&data), plane(&data)), 0.0);
+        assert_eq!(psnr(mse(plane(&data), plane(&data))), MAX_PSNR);
+        assert!((ssim(plane(&data), plane(&data)) - 1.0).abs() < 1e-9);
+        assert!((ms_ssim(plane(&data), plane(&data)) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn constant_offset() {
+        let reference = gradient(0);
+        let distorted = gradient(4);
+        let mse = mse(plane(&reference), plane(&distorted));
+        assert_eq!(mse, 16.0);
+        let expected = 10.0 * (255.0f64 * 255.0 / 16.0).log10();
+        assert!((psnr(mse) - expected).abs() < 1e-9);
+        assert!(ssim(plane(&reference), plane(&distorted)) < 1.0);
+    }
+
+    #[test]
+    fn summary_percentiles() {
+        let frames = (0..=100)
+            .rev()
+            .map(|value| Metrics::from_values([value as f64; 6]))
+            .collect::<Vec<_>>();
+        let summary = Summary::from_frames(&frames);
+        assert_eq!(summary.mean.values(), [50.0; 6]);
+        assert_eq!(summary.min.values(), [0.0; 6]);
+        assert_eq!(summary.p1.values(), [1.0; 6]);
+        assert_eq!(summary.p5.values(), [5.0; 6]);
+        assert_eq!(summary.p50.values(), [50.0; 6]);
+        assert_eq!(Summary::from_frames(&[]).mean.values(), [0.0; 6]);
+    }
+}
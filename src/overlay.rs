@@ -0,0 +1,196 @@
+use wgpu::util::DeviceExt;
+
+use crate::font;
+
+pub type Color = [f32; 4];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct OverlayVertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+impl OverlayVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<OverlayVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+struct Rect {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    color: Color,
+}
+
+/// Solid rectangles and text drawn on top of the videos. Everything is laid
+/// out in physical pixels from the top left corner of the target.
+pub struct Overlay {
+    render_pipeline: wgpu::RenderPipeline,
+    rects: Vec<Rect>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    num_vertices: u32,
+}
+
+impl Overlay {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Overlay shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("overlay.wgsl").into()),
+        });
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Overlay pipeline layout"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("overlay pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[OverlayVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        Self {
+            render_pipeline,
+            rects: Vec::new(),
+            vertex_buffer: None,
+            num_vertices: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Color) {
+        self.rects.push(Rect {
+            x,
+            y,
+            width,
+            height,
+            color,
+        });
+    }
+
+    /// Draw `text` with its top left corner at `x`, `y`, each font pixel being
+    /// `scale` physical pixels.
+    pub fn text(&mut self, x: f32, y: f32, scale: f32, text: &str, color: Color) {
+        let (glyph_width, glyph_height) = font::GLYPH_SIZE;
+        for (index, character) in text.chars().enumerate() {
+            let left = x + (index as u32 * font::ADVANCE) as f32 * scale;
+            for column in 0..glyph_width {
+                for row in 0..glyph_height {
+                    if font::is_set(character, column, row) {
+                        self.rect(
+                            left + column as f32 * scale,
+                            y + row as f32 * scale,
+                            scale,
+                            scale,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn text_size(text: &str, scale: f32) -> (f32, f32) {
+        let width = (text.chars().count() as u32 * font::ADVANCE) as f32 * scale;
+        (width, font::GLYPH_SIZE.1 as f32 * scale)
+    }
+
+    /// Upload everything added since the last `clear` for a target of `size`.
+    pub fn prepare(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        let to_clip =
+            |x: f32, y: f32| [x / size.0 as f32 * 2.0 - 1.0, 1.0 - y / size.1 as f32 * 2.0];
+        let vertices = self
+            .rects
+            .iter()
+            .flat_map(|rect| {
+                let top_left = to_clip(rect.x, rect.y);
+                let bottom_right = to_clip(rect.x + rect.width, rect.y + rect.height);
+                let top_right = [bottom_right[0], top_left[1]];
+                let bottom_left = [top_left[0], bottom_right[1]];
+                [
+                    top_left,
+                    bottom_left,
+                    bottom_right,
+                    top_left,
+                    bottom_right,
+                    top_right,
+                ]
+                .map(|position| OverlayVertex {
+                    position,
+                    color: rect.color,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        self.num_vertices = vertices.len() as u32;
+        self.vertex_buffer = if vertices.is_empty() {
+            None
+        } else {
+            Some(
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Overlay vertex buffer"),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                }),
+            )
+        };
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some(vertex_buffer) = &self.vertex_buffer {
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.draw(0..self.num_vertices, 0..1);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+
+use ffmpeg_next::{format::Pixel, util::frame::Video, Rational};
+
+use crate::info::{MediaInfo, StreamInfo};
+use crate::live::LiveStatus;
+use crate::source::FrameSource;
+
+/// Bytes of the most recent frames of a pipe kept to seek back to, about 80
+/// frames of 1080p 4:2:0 video.
+const BUFFER_SIZE: usize = 256 << 20;
+
+/// Whether `path` is stdin or a named pipe, which can only be read forwards.
+pub fn is_pipe(path: &str) -> bool {
+    if path == "-" {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            return metadata.file_type().is_fifo();
+        }
+    }
+    false
+}
+
+/// A source read from a pipe or a live stream. Seeking forwards reads ahead,
+/// seeking backwards only reaches the frames still held in memory, the most
+/// recent [`BUFFER_SIZE`] bytes of them.
+pub struct Piped {
+    source: Box<dyn FrameSource>,
+    /// The most recent frames read with their indices, oldest first.
+    frames: VecDeque<(usize, Video)>,
+    /// Bytes of picture data in `frames`.
+    buffered_size: usize,
+    frame_index: Option<usize>,
+}
+
+impl Piped {
+    pub fn new(source: Box<dyn FrameSource>) -> Self {
+        Self {
+            source,
+            frames: VecDeque::new(),
+            buffered_size: 0,
+            frame_index: None,
+        }
+    }
+
+    /// Read the next frame from the pipe and keep it.
+    fn read(&mut self) -> Option<Video> {
+        let frame = self.source.next_frame()?;
+        self.buffered_size += size(&frame);
+        self.frames
+            .push_back((self.source.frame_index(), frame.clone()));
+        // Always keep the latest frame, however large.
+        while self.buffered_size > BUFFER_SIZE && self.frames.len() > 1 {
+            let (_, oldest) = self.frames.pop_front().expect("a buffered frame");
+            self.buffered_size -= size(&oldest);
+        }
+        Some(frame)
+    }
+
+    fn buffered(&self, index: usize) -> Option<Video> {
+        self.frames
+            .iter()
+            .find(|(buffered, _)| *buffered == index)
+            .map(|(_, frame)| frame.clone())
+    }
+
+    fn latest(&self) -> Option<usize> {
+        self.frames.back().map(|&(index, _)| index)
+    }
+}
+
+impl FrameSource for Piped {
+    fn next_frame(&mut self) -> Option<Video> {
+        // After seeking back, replay the buffer before reading on.
+        if let Some(current) = self.frame_index {
+            if matches!(self.latest(), Some(latest) if current < latest) {
+                let (index, frame) = self
+                    .frames
+                    .iter()
+                    .find(|(index, _)| *index > current)
+                    .cloned()
+                    .expect("a later buffered frame");
+                self.frame_index = Some(index);
+                return Some(frame);
+            }
+        }
+        let frame = self.read()?;
+        self.frame_index = self.latest();
+        Some(frame)
+    }
+
+    fn seek(&mut self, index: usize) -> Option<Video> {
+        if let Some(frame) = self.buffered(index) {
+            self.frame_index = Some(index);
+            return Some(frame);
+        }
+        if matches!(self.latest(), Some(latest) if index < latest) {
+            return None;
+        }
+        loop {
+            let frame = self.read()?;
+            if self.source.frame_index() >= index {
+                self.frame_index = self.latest();
+                return Some(frame);
+            }
+        }
+    }
+
+    fn frame_index(&self) -> usize {
+        self.frame_index.unwrap_or(0)
+    }
+
+    fn frame_rate(&self) -> Rational {
+        self.source.frame_rate()
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.source.dimensions()
+    }
+
+    fn format(&self) -> Pixel {
+        self.source.format()
+    }
+
+    fn live_status(&self) -> Option<LiveStatus> {
+        self.source.live_status()
+    }
+
+    fn streams(&self) -> Vec<StreamInfo> {
+        self.source.streams()
+    }
+
+    fn media_info(&self) -> Option<MediaInfo> {
+        self.source.media_info()
+    }
+}
+
+/// Bytes of picture data of `frame`.
+fn size(frame: &Video) -> usize {
+    (0..frame.planes())
+        .map(|plane| frame.data(plane).len())
+        .sum()
+}
@@ -0,0 +1,244 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use ffmpeg_next::{format::Pixel, util::frame::Video, Rational};
+
+use crate::source::FrameSource;
+
+/// Headerless video, every frame stored as its planes one after the other
+/// without padding. Since all frames have the same size, frame `index` starts
+/// at `index` times that size and seeking is exact and instant.
+pub struct RawVideo {
+    file: File,
+    format: Pixel,
+    dimensions: (u32, u32),
+    frame_rate: Rational,
+    /// Bytes per row of every plane.
+    row_sizes: Vec<usize>,
+    frame_size: u64,
+    frame_count: usize,
+    frame_index: Option<usize>,
+}
+
+impl RawVideo {
+    pub fn open(path: &str, format: Pixel, dimensions: (u32, u32), frame_rate: Rational) -> Self {
+        let file = File::open(path).expect("to read the input");
+        let row_sizes = row_sizes(format, dimensions);
+        let frame_size = frame_size(format, dimensions, &row_sizes) as u64;
+        let length = file.metadata().expect("input metadata").len();
+
+        Self {
+            file,
+            format,
+            dimensions,
+            frame_rate,
+            row_sizes,
+            frame_size,
+            frame_count: (length / frame_size) as usize,
+            frame_index: None,
+        }
+    }
+
+    /// Number of complete frames in the file.
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    fn read_frame(&mut self, index: usize) -> Option<Video> {
+        if index >= self.frame_count {
+            return None;
+        }
+        let mut data = vec![0; self.frame_size as usize];
+        self.file
+            .seek(SeekFrom::Start(index as u64 * self.frame_size))
+            .expect("seek in the input");
+        self.file.read_exact(&mut data).expect("read a frame");
+
+        let mut frame = unpack(self.format, self.dimensions, &self.row_sizes, &data);
+        frame.set_pts(Some(index as i64));
+        self.frame_index = Some(index);
+        Some(frame)
+    }
+}
+
+impl FrameSource for RawVideo {
+    fn next_frame(&mut self) -> Option<Video> {
+        let index = self.frame_index.map_or(0, |index| index + 1);
+        self.read_frame(index)
+    }
+
+    fn seek(&mut self, index: usize) -> Option<Video> {
+        self.read_frame(index)
+    }
+
+    fn frame_index(&self) -> usize {
+        self.frame_index.unwrap_or(0)
+    }
+
+    fn frame_rate(&self) -> Rational {
+        self.frame_rate
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    fn format(&self) -> Pixel {
+        self.format
+    }
+}
+
+/// Bytes per row of every plane of packed frames.
+pub(crate) fn row_sizes(format: Pixel, dimensions: (u32, u32)) -> Vec<usize> {
+    let template = Video::new(format, dimensions.0, dimensions.1);
+    (0..template.planes())
+        .map(|plane| unsafe {
+            ffmpeg_next::ffi::av_image_get_linesize(
+                format.into(),
+                dimensions.0 as i32,
+                plane as i32,
+            ) as usize
+        })
+        .collect()
+}
+
+/// Bytes of a packed frame with rows of `row_sizes`.
+pub(crate) fn frame_size(format: Pixel, dimensions: (u32, u32), row_sizes: &[usize]) -> usize {
+    let template = Video::new(format, dimensions.0, dimensions.1);
+    row_sizes
+        .iter()
+        .enumerate()
+        .map(|(plane, row_size)| row_size * template.plane_height(plane) as usize)
+        .sum()
+}
+
+/// Copy the planes packed one after the other in `data` into a frame.
+pub(crate) fn unpack(
+    format: Pixel,
+    dimensions: (u32, u32),
+    row_sizes: &[usize],
+    data: &[u8],
+) -> Video {
+    let mut frame = Video::new(format, dimensions.0, dimensions.1);
+    let mut offset = 0;
+    for (plane, &row_size) in row_sizes.iter().enumerate() {
+        let stride = frame.stride(plane);
+        for row in 0..frame.plane_height(plane) as usize {
+            frame.data_mut(plane)[row * stride..row * stride + row_size]
+                .copy_from_slice(&data[offset..offset + row_size]);
+            offset += row_size;
+        }
+    }
+    frame
+}
+
+/// Write the planes of `frame` one after the other without padding.
+pub(crate) fn pack(frame: &Video, row_sizes: &[usize], writer: &mut impl std::io::Write) {
+    for (plane, &row_size) in row_sizes.iter().enumerate() {
+        let stride = frame.stride(plane);
+        for row in 0..frame.plane_height(plane) as usize {
+            writer
+                .write_all(&frame.data(plane)[row * stride..row * stride + row_size])
+                .expect("write a frame");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temporary file holding `data`, named after the test.
+    fn raw_file(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.yuv", name, std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    /// Frame `index` of 4x2 yuv420p with every plane filled with its own
+    /// value.
+    fn yuv420p_frame(index: u8) -> Vec<u8> {
+        let mut frame = vec![10 + index; 8];
+        frame.extend([20 + index; 2]);
+        frame.extend([30 + index; 2]);
+        frame
+    }
+
+    #[test]
+    fn frame_size_of_yuv420p() {
+        let rows = row_sizes(Pixel::YUV420P, (4, 2));
+        assert_eq!(rows, [4, 2, 2]);
+        assert_eq!(frame_size(Pixel::YUV420P, (4, 2), &rows), 12);
+        // Chroma rounds up for odd sizes.
+        let rows = row_sizes(Pixel::YUV420P, (5, 3));
+        assert_eq!(rows, [5, 3, 3]);
+        assert_eq!(frame_size(Pixel::YUV420P, (5, 3), &rows), 15 + 6 + 6);
+    }
+
+    #[test]
+    fn frame_size_of_10_bit() {
+        let rows = row_sizes(Pixel::YUV420P10LE, (4, 2));
+        assert_eq!(rows, [8, 4, 4]);
+        assert_eq!(frame_size(Pixel::YUV420P10LE, (4, 2), &rows), 24);
+    }
+
+    #[test]
+    fn reads_planes_and_seeks() {
+        let mut data = (0..4).flat_map(yuv420p_frame).collect::<Vec<_>>();
+        // A short final frame is left out.
+        data.extend([0; 5]);
+        let path = raw_file("raw-seek", &data);
+        let mut video = RawVideo::open(
+            path.to_str().unwrap(),
+            Pixel::YUV420P,
+            (4, 2),
+            Rational::new(25, 1),
+        );
+        assert_eq!(video.frame_count(), 4);
+
+        let frame = video.next_frame().unwrap();
+        assert_eq!(video.frame_index(), 0);
+        let stride = frame.stride(0);
+        assert_eq!(&frame.data(0)[..4], [10; 4]);
+        assert_eq!(&frame.data(0)[stride..stride + 4], [10; 4]);
+        assert_eq!(&frame.data(1)[..2], [20; 2]);
+        assert_eq!(&frame.data(2)[..2], [30; 2]);
+
+        let frame = video.seek(2).unwrap();
+        assert_eq!(video.frame_index(), 2);
+        assert_eq!(frame.data(0)[0], 12);
+        assert_eq!(frame.data(2)[0], 32);
+        let frame = video.next_frame().unwrap();
+        assert_eq!(video.frame_index(), 3);
+        assert_eq!(frame.data(1)[0], 23);
+        assert!(video.next_frame().is_none());
+        assert!(video.seek(4).is_none());
+
+        let frame = video.seek(0).unwrap();
+        assert_eq!(frame.data(0)[0], 10);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn reads_10_bit_samples() {
+        let sample = |value: u16| value.to_le_bytes();
+        let mut data = (0..8).flat_map(|_| sample(1000)).collect::<Vec<_>>();
+        data.extend((0..2).flat_map(|_| sample(512)));
+        data.extend((0..2).flat_map(|_| sample(64)));
+        let path = raw_file("raw-10-bit", &data);
+        let mut video = RawVideo::open(
+            path.to_str().unwrap(),
+            Pixel::YUV420P10LE,
+            (4, 2),
+            Rational::new(25, 1),
+        );
+        assert_eq!(video.frame_count(), 1);
+
+        let frame = video.next_frame().unwrap();
+        let stride = frame.stride(0);
+        assert_eq!(&frame.data(0)[stride + 6..stride + 8], sample(1000));
+        assert_eq!(&frame.data(1)[2..4], sample(512));
+        assert_eq!(&frame.data(2)[..2], sample(64));
+        std::fs::remove_file(&path).ok();
+    }
+}
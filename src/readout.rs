@@ -0,0 +1,387 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use ffmpeg_next::{format::Pixel, util::frame::Video};
+
+use crate::font;
+use crate::metrics::{self, Plane, Psnr};
+use crate::overlay::{Color, Overlay};
+use crate::source::Converter;
+use crate::texture::VideoTexture;
+
+const TEXT_SCALE: f32 = 2.0;
+pub(crate) const GRAPH_HEIGHT: f32 = 120.0;
+/// Width of a single frame in the graph in physical pixels.
+const FRAME_WIDTH: f32 = 2.0;
+const BACKGROUND: Color = [0.0, 0.0, 0.0, 0.6];
+const PLAYHEAD: Color = [1.0, 1.0, 1.0, 0.8];
+const COLORS: [Color; 6] = [
+    [1.0, 1.0, 1.0, 1.0],
+    [1.0, 0.35, 0.3, 1.0],
+    [0.3, 0.85, 0.35, 1.0],
+    [0.35, 0.6, 1.0, 1.0],
+    [1.0, 0.8, 0.2, 1.0],
+    [0.85, 0.4, 1.0, 1.0],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum GraphMetric {
+    Psnr,
+    Ssim,
+}
+
+impl GraphMetric {
+    fn label(self) -> &'static str {
+        match self {
+            GraphMetric::Psnr => "PSNR Y 20-50 dB",
+            GraphMetric::Ssim => "SSIM 0.8-1.0",
+        }
+    }
+
+    /// Position of `sample` between the bottom (0) and the top (1) of the graph.
+    fn normalize(self, sample: &Sample) -> f32 {
+        let value = match self {
+            GraphMetric::Psnr => (sample.psnr.y - 20.0) / 30.0,
+            GraphMetric::Ssim => (sample.ssim - 0.8) / 0.2,
+        };
+        value.clamp(0.0, 1.0) as f32
+    }
+}
+
+struct Sample {
+    psnr: Psnr,
+    ssim: f64,
+}
+
+/// Metrics of every video by frame index of the reference.
+struct History {
+    reference: usize,
+    samples: Vec<BTreeMap<usize, Sample>>,
+}
+
+/// Frames shown of every video to measure against video `reference`.
+struct Job {
+    reference: usize,
+    frame: usize,
+    frames: Vec<Video>,
+}
+
+/// The job not yet taken by the background thread, if any.
+#[derive(Default)]
+struct Pending {
+    job: Option<Job>,
+    /// Whether the background thread is measuring a job.
+    busy: bool,
+    stopped: bool,
+}
+
+impl Pending {
+    fn is_idle(&self) -> bool {
+        self.job.is_none() && !self.busy
+    }
+}
+
+/// PSNR and SSIM of every video against a reference while playing, shown as
+/// text in the top left corner and as a graph along the bottom of the window.
+/// Frames are measured on a background thread, skipping those shown while it
+/// is busy, see [`MetricsReadout::wait`] for captures.
+pub struct MetricsReadout {
+    reference: usize,
+    graph_metric: GraphMetric,
+    history: Arc<Mutex<History>>,
+    pending: Arc<(Mutex<Pending>, Condvar)>,
+    current_frame: usize,
+}
+
+impl MetricsReadout {
+    pub fn new(count: usize, reference: usize) -> Self {
+        let reference = reference.min(count.saturating_sub(1));
+        let history = Arc::new(Mutex::new(History {
+            reference,
+            samples: (0..count).map(|_| BTreeMap::new()).collect(),
+        }));
+        let pending = Arc::new((Mutex::new(Pending::default()), Condvar::new()));
+        let thread_history = history.clone();
+        let thread_pending = pending.clone();
+        std::thread::spawn(move || {
+            let mut converters = Vec::new();
+            while let Some(job) = next_job(&thread_pending) {
+                measure(&job, &mut converters, &thread_history);
+                let (pending, done) = &*thread_pending;
+                pending.lock().unwrap().busy = false;
+                done.notify_all();
+            }
+        });
+        Self {
+            reference,
+            graph_metric: GraphMetric::Psnr,
+            history,
+            pending,
+            current_frame: 0,
+        }
+    }
+
+    fn history(&self) -> MutexGuard<'_, History> {
+        self.history.lock().unwrap()
+    }
+
+    pub fn reference(&self) -> usize {
+        self.reference
+    }
+
+    /// Measure against the next video instead, starting over with an empty graph.
+    pub fn next_reference(&mut self) {
+        let mut history = self.history.lock().unwrap();
+        self.reference = (self.reference + 1) % history.samples.len().max(1);
+        history.reference = self.reference;
+        history.samples.iter_mut().for_each(BTreeMap::clear);
+    }
+
+    /// Forget the metrics of video `index`, which switched to another stream.
+    pub fn input_changed(&mut self, index: usize) {
+        let mut history = self.history();
+        if index == self.reference {
+            history.samples.iter_mut().for_each(BTreeMap::clear);
+        } else if let Some(samples) = history.samples.get_mut(index) {
+            samples.clear();
+        }
+    }
+
+    pub fn toggle_graph_metric(&mut self) {
+        self.graph_metric = match self.graph_metric {
+            GraphMetric::Psnr => GraphMetric::Ssim,
+            GraphMetric::Ssim => GraphMetric::Psnr,
+        };
+    }
+
+    /// Measure the frames currently shown by `textures` against the
+    /// reference, unless they have been already or the background thread is
+    /// busy, in which case a later call does.
+    pub fn update(&mut self, textures: &[&VideoTexture]) {
+        if textures.len() < 2 {
+            return;
+        }
+        self.current_frame = textures[self.reference].source.frame_index();
+        if self.is_measured() {
+            return;
+        }
+
+        let (pending, ready) = &*self.pending;
+        let mut pending = pending.lock().unwrap();
+        if !pending.is_idle() {
+            return;
+        }
+        pending.job = Some(Job {
+            reference: self.reference,
+            frame: self.current_frame,
+            frames: textures
+                .iter()
+                .map(|texture| texture.frame.clone())
+                .collect(),
+        });
+        ready.notify_all();
+    }
+
+    /// Measure the frames currently shown by `textures` and wait until that
+    /// is done, so that captures show the same metrics however long it
+    /// takes.
+    pub fn wait(&mut self, textures: &[&VideoTexture]) {
+        if textures.len() < 2 {
+            return;
+        }
+        loop {
+            self.update(textures);
+            if self.is_measured() {
+                return;
+            }
+            let (pending, done) = &*self.pending;
+            let mut pending = pending.lock().unwrap();
+            while !pending.is_idle() {
+                pending = done.wait(pending).unwrap();
+            }
+        }
+    }
+
+    /// Whether the frames of `current_frame` have been measured.
+    fn is_measured(&self) -> bool {
+        self.history()
+            .samples
+            .iter()
+            .enumerate()
+            .all(|(index, samples)| {
+                index == self.reference || samples.contains_key(&self.current_frame)
+            })
+    }
+
+    /// Add the readout for a window of `size` to `overlay`, labelling every
+    /// video with the matching entry of `labels`.
+    pub fn draw(&self, overlay: &mut Overlay, size: (u32, u32), labels: &[String]) {
+        let history = self.history();
+        if history.samples.len() < 2 {
+            return;
+        }
+
+        for (index, label) in labels.iter().enumerate() {
+            self.draw_label(overlay, &history, index, index, label);
+        }
+
+        let top = size.1 as f32 - GRAPH_HEIGHT;
+        let first = self.first_visible_frame(size);
+        let visible = first..first + visible_frames(size);
+        overlay.rect(0.0, top, size.0 as f32, GRAPH_HEIGHT, BACKGROUND);
+        for (index, samples) in history.samples.iter().enumerate() {
+            for (&frame, sample) in samples.range(visible.clone()) {
+                let value = self.graph_metric.normalize(sample);
+                overlay.rect(
+                    (frame - first) as f32 * FRAME_WIDTH,
+                    top + (1.0 - value) * (GRAPH_HEIGHT - FRAME_WIDTH),
+                    FRAME_WIDTH,
+                    FRAME_WIDTH,
+                    color(index),
+                );
+            }
+        }
+        overlay.rect(
+            (self.current_frame - first) as f32 * FRAME_WIDTH,
+            top,
+            1.0,
+            GRAPH_HEIGHT,
+            PLAYHEAD,
+        );
+        overlay.text(4.0, top + 4.0, 1.0, self.graph_metric.label(), PLAYHEAD);
+    }
+
+    /// Add only the label of video `index` to `overlay`, for when it is shown
+    /// on its own.
+    pub fn draw_single(&self, overlay: &mut Overlay, index: usize, label: &str) {
+        self.draw_label(overlay, &self.history(), index, 0, label);
+    }
+
+    /// Label of video `index` with its metrics on the given `line` of text.
+    fn draw_label(
+        &self,
+        overlay: &mut Overlay,
+        history: &History,
+        index: usize,
+        line: usize,
+        label: &str,
+    ) {
+        let text = if index == self.reference {
+            format!("{} (reference)", label)
+        } else {
+            match history.samples[index].get(&self.current_frame) {
+                Some(sample) => format!(
+                    "{}  PSNR {:.2} dB  SSIM {:.4}",
+                    label, sample.psnr.y, sample.ssim
+                ),
+                None => label.to_string(),
+            }
+        };
+        let line_height = (font::GLYPH_SIZE.1 + 4) as f32 * TEXT_SCALE;
+        let y = TEXT_SCALE * 4.0 + line as f32 * line_height;
+        let (width, height) = Overlay::text_size(&text, TEXT_SCALE);
+        overlay.rect(
+            0.0,
+            y - TEXT_SCALE * 2.0,
+            width + TEXT_SCALE * 8.0,
+            height + TEXT_SCALE * 4.0,
+            BACKGROUND,
+        );
+        overlay.text(TEXT_SCALE * 4.0, y, TEXT_SCALE, &text, color(index));
+    }
+
+    /// Frame under `position` if it is inside the graph.
+    pub fn frame_at(&self, position: (f64, f64), size: (u32, u32)) -> Option<usize> {
+        if self.history().samples.len() < 2 || (position.1 as f32) < size.1 as f32 - GRAPH_HEIGHT {
+            return None;
+        }
+        Some(self.first_visible_frame(size) + (position.0 as f32 / FRAME_WIDTH) as usize)
+    }
+
+    /// Once the graph is full it scrolls, keeping the playhead three quarters
+    /// of the way across.
+    fn first_visible_frame(&self, size: (u32, u32)) -> usize {
+        self.current_frame
+            .saturating_sub(visible_frames(size) * 3 / 4)
+    }
+}
+
+impl Drop for MetricsReadout {
+    fn drop(&mut self) {
+        let (pending, ready) = &*self.pending;
+        pending.lock().unwrap().stopped = true;
+        ready.notify_all();
+    }
+}
+
+/// Wait for the next job, `None` once the readout is dropped.
+fn next_job(pending: &(Mutex<Pending>, Condvar)) -> Option<Job> {
+    let (pending, ready) = pending;
+    let mut pending = pending.lock().unwrap();
+    loop {
+        if pending.stopped {
+            return None;
+        }
+        if let Some(job) = pending.job.take() {
+            pending.busy = true;
+            return Some(job);
+        }
+        pending = ready.wait(pending).unwrap();
+    }
+}
+
+/// Measure the frames of `job` into `history`, converting them to YUV at the
+/// size of the reference with `converters`.
+fn measure(job: &Job, converters: &mut Vec<((u32, u32), Converter)>, history: &Mutex<History>) {
+    let reference = &job.frames[job.reference];
+    let dimensions = (reference.width(), reference.height());
+    converters.truncate(job.frames.len());
+    let frames = job
+        .frames
+        .iter()
+        .enumerate()
+        .map(|(index, frame)| {
+            // Everything is scaled to the size of the reference.
+            if converters.get(index).map(|(size, _)| *size) != Some(dimensions) {
+                let converter = (dimensions, Converter::new(Pixel::YUV420P, Some(dimensions)));
+                if index < converters.len() {
+                    converters[index] = converter;
+                } else {
+                    converters.push(converter);
+                }
+            }
+            converters[index].1.convert(frame)
+        })
+        .collect::<Vec<_>>();
+
+    let reference = &frames[job.reference];
+    let samples = frames
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != job.reference)
+        .map(|(index, frame)| {
+            let sample = Sample {
+                psnr: Psnr::from_frames(reference, frame),
+                ssim: metrics::ssim(Plane::from_frame(reference, 0), Plane::from_frame(frame, 0)),
+            };
+            (index, sample)
+        })
+        .collect::<Vec<_>>();
+
+    let mut history = history.lock().unwrap();
+    // Measured against a reference no longer used.
+    if history.reference != job.reference {
+        return;
+    }
+    for (index, sample) in samples {
+        history.samples[index].insert(job.frame, sample);
+    }
+}
+
+fn visible_frames(size: (u32, u32)) -> usize {
+    (size.0 as f32 / FRAME_WIDTH) as usize
+}
+
+fn color(index: usize) -> Color {
+    COLORS[index % COLORS.len()]
+}
@@ -0,0 +1,198 @@
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tungstenite::{Message, WebSocket};
+
+use crate::server::{self, Role};
+
+/// Size of the comparison sent to remote viewers.
+pub const SIZE: (u32, u32) = (1280, 720);
+/// Time between frames sent to remote viewers at most.
+const FRAME_INTERVAL: Duration = Duration::from_millis(66);
+const JPEG_QUALITY: u8 = 80;
+/// How long a viewer connection waits for controls before checking for a
+/// new frame.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Playback controls sent back by remote viewers, as the text messages
+/// `toggle`, `next`, `previous`, `reference`, `next-difference`,
+/// `previous-difference` and `seek N`.
+#[derive(Clone, Copy, Debug)]
+pub enum Control {
+    TogglePlayback,
+    NextFrame,
+    PreviousFrame,
+    NextReference,
+    NextDifference,
+    PreviousDifference,
+    Seek(usize),
+}
+
+impl Control {
+    fn parse(message: &str) -> Option<Self> {
+        let mut words = message.split_whitespace();
+        let control = match words.next()? {
+            "toggle" => Control::TogglePlayback,
+            "next" => Control::NextFrame,
+            "previous" => Control::PreviousFrame,
+            "reference" => Control::NextReference,
+            "next-difference" => Control::NextDifference,
+            "previous-difference" => Control::PreviousDifference,
+            "seek" => Control::Seek(words.next()?.parse().ok()?),
+            _ => return None,
+        };
+        Some(control)
+    }
+}
+
+/// The latest comparison encoded as JPEG, numbered so connections can tell
+/// whether they sent it already.
+type SharedFrame = Arc<Mutex<(u64, Arc<Vec<u8>>)>>;
+
+/// Tightly packed RGBA rows and their size.
+type Captured = (Vec<u8>, (u32, u32));
+
+/// Browsers following the viewer through the page in client/, which shows
+/// the comparison as the viewer draws it and sends [`Control`]s back.
+pub struct RemoteViewer {
+    captured: Sender<Captured>,
+    /// Whether the encoder is still busy with the last capture.
+    encoding: Arc<AtomicBool>,
+    controls: Receiver<Control>,
+    viewers: Arc<AtomicUsize>,
+    last_frame: Option<Instant>,
+}
+
+impl RemoteViewer {
+    /// Serve the page at `address` in the background.
+    pub fn serve(address: &str) -> std::io::Result<Self> {
+        let frame = SharedFrame::default();
+        let (sender, controls) = mpsc::channel();
+        let viewers = Arc::new(AtomicUsize::new(0));
+        {
+            let frame = frame.clone();
+            let viewers = viewers.clone();
+            server::spawn(address, Role::Viewer, move |socket| {
+                view(socket, frame.clone(), sender.clone(), viewers.clone())
+            })?;
+        }
+        println!("serving the remote viewer at http://{}/", address);
+
+        let (captured, encoder) = mpsc::channel();
+        let encoding = Arc::new(AtomicBool::new(false));
+        {
+            let encoding = encoding.clone();
+            std::thread::spawn(move || encode(&encoder, &frame, &encoding));
+        }
+
+        Ok(Self {
+            captured,
+            encoding,
+            controls,
+            viewers,
+            last_frame: None,
+        })
+    }
+
+    /// Whether anyone is watching, the last frame is encoded and it is time
+    /// for the next one, so the comparison is only captured when it is sent.
+    pub fn wants_frame(&self) -> bool {
+        self.viewers.load(Ordering::Relaxed) > 0
+            && !self.encoding.load(Ordering::Acquire)
+            && !matches!(self.last_frame, Some(last) if last.elapsed() < FRAME_INTERVAL)
+    }
+
+    /// Send a comparison of tightly packed RGBA rows at `size` to all viewers,
+    /// encoding it in the background.
+    pub fn send(&mut self, rgba: Vec<u8>, size: (u32, u32)) {
+        self.encoding.store(true, Ordering::Release);
+        if self.captured.send((rgba, size)).is_err() {
+            self.encoding.store(false, Ordering::Release);
+        }
+        self.last_frame = Some(Instant::now());
+    }
+
+    /// Controls received since the last call.
+    pub fn controls(&self) -> impl Iterator<Item = Control> + '_ {
+        self.controls.try_iter()
+    }
+}
+
+/// Encode captures as JPEG and share them with the connections until the
+/// viewer is gone.
+fn encode(captured: &Receiver<Captured>, frame: &SharedFrame, encoding: &AtomicBool) {
+    for (rgba, size) in captured {
+        let mut jpeg = Vec::new();
+        jpeg_encoder::Encoder::new(&mut jpeg, JPEG_QUALITY)
+            .encode(
+                &rgba,
+                size.0 as u16,
+                size.1 as u16,
+                jpeg_encoder::ColorType::Rgba,
+            )
+            .expect("encode jpeg");
+        let mut frame = frame.lock().unwrap();
+        *frame = (frame.0 + 1, Arc::new(jpeg));
+        encoding.store(false, Ordering::Release);
+    }
+}
+
+/// Send new frames to a viewer and pass on its controls until it leaves.
+fn view(
+    mut socket: WebSocket<TcpStream>,
+    frame: SharedFrame,
+    controls: Sender<Control>,
+    viewers: Arc<AtomicUsize>,
+) {
+    if socket
+        .get_mut()
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .is_err()
+    {
+        return;
+    }
+    viewers.fetch_add(1, Ordering::Relaxed);
+    let mut sent = 0;
+    loop {
+        let (number, jpeg) = frame.lock().unwrap().clone();
+        if number != sent {
+            sent = number;
+            if !still_open(socket.write_message(Message::Binary(jpeg.to_vec()))) {
+                break;
+            }
+        }
+        match socket.read_message() {
+            Ok(Message::Text(message)) => {
+                if let Some(control) = Control::parse(&message) {
+                    if controls.send(control).is_err() {
+                        break;
+                    }
+                }
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(error) => {
+                if !still_open(Err(error)) {
+                    break;
+                }
+            }
+        }
+    }
+    viewers.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Whether the connection survived an operation, which may only have timed
+/// out waiting.
+fn still_open(result: tungstenite::Result<()>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(tungstenite::Error::Io(error)) => {
+            matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+        }
+        Err(_) => false,
+    }
+}
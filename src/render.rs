@@ -0,0 +1,163 @@
+use ffmpeg_next::{codec, encoder, format::Pixel, util::frame::Video, Packet, Rational};
+
+use crate::renderer::Renderer;
+use crate::source::Converter;
+use crate::y4m::{self, Y4mWriter};
+
+/// Encodes YUV 4:2:0 frames to a video file, in a container guessed from the
+/// extension of the path.
+pub struct VideoWriter {
+    output: ffmpeg_next::format::context::Output,
+    encoder: encoder::Video,
+    stream_index: usize,
+    time_base: Rational,
+    frame_count: i64,
+}
+
+impl VideoWriter {
+    /// Create `path` for frames of `size` at `frame_rate`. H.264 is used when
+    /// ffmpeg was built with an encoder for it, MPEG-4 part 2 otherwise.
+    pub fn new(path: &str, size: (u32, u32), frame_rate: Rational) -> Self {
+        let mut output = ffmpeg_next::format::output(&path).expect("to create the output");
+        let global_header = output
+            .format()
+            .flags()
+            .contains(ffmpeg_next::format::Flags::GLOBAL_HEADER);
+        let codec = encoder::find(codec::Id::H264)
+            .or_else(|| encoder::find(codec::Id::MPEG4))
+            .expect("a video encoder");
+
+        let time_base = frame_rate.invert();
+        let mut stream = output.add_stream(codec).expect("output stream");
+        let stream_index = stream.index();
+        let mut video = codec::context::Context::new()
+            .encoder()
+            .video()
+            .expect("video encoder");
+        video.set_width(size.0);
+        video.set_height(size.1);
+        video.set_format(Pixel::YUV420P);
+        video.set_time_base(time_base);
+        video.set_frame_rate(Some(frame_rate));
+        video.set_bit_rate(8_000_000);
+        if global_header {
+            video.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = video.open_as(codec).expect("open the encoder");
+        stream.set_time_base(time_base);
+        stream.set_parameters(&encoder);
+        output.write_header().expect("write header");
+
+        Self {
+            output,
+            encoder,
+            stream_index,
+            time_base,
+            frame_count: 0,
+        }
+    }
+
+    /// Encode one frame of the size given when creating the file.
+    pub fn write(&mut self, mut frame: Video) {
+        frame.set_pts(Some(self.frame_count));
+        self.frame_count += 1;
+        self.encoder.send_frame(&frame).expect("send frame");
+        self.write_packets();
+    }
+
+    /// Flush the encoder and finish the file.
+    pub fn finish(mut self) {
+        self.encoder.send_eof().expect("send eof");
+        self.write_packets();
+        self.output.write_trailer().expect("write trailer");
+    }
+
+    fn write_packets(&mut self) {
+        // The muxer may have changed the time base of the stream in write_header.
+        let stream_time_base = self
+            .output
+            .stream(self.stream_index)
+            .expect("output stream")
+            .time_base();
+        let mut packet = Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(self.stream_index);
+            packet.rescale_ts(self.time_base, stream_time_base);
+            packet
+                .write_interleaved(&mut self.output)
+                .expect("write packet");
+        }
+    }
+}
+
+/// Where rendered frames go.
+enum Output {
+    Encoded(VideoWriter),
+    Y4m(Y4mWriter),
+}
+
+impl Output {
+    fn write(&mut self, frame: Video) {
+        match self {
+            Output::Encoded(writer) => writer.write(frame),
+            Output::Y4m(writer) => writer.write(&frame),
+        }
+    }
+
+    fn finish(self) {
+        match self {
+            Output::Encoded(writer) => writer.finish(),
+            Output::Y4m(writer) => writer.finish(),
+        }
+    }
+}
+
+/// Frame of the tightly packed RGBA rows of an image of `size`.
+fn rgba_frame(rgba: &[u8], size: (u32, u32)) -> Video {
+    let mut frame = Video::new(Pixel::RGBA, size.0, size.1);
+    let row_bytes = 4 * size.0 as usize;
+    let stride = frame.stride(0);
+    for (row, pixels) in rgba.chunks(row_bytes).enumerate() {
+        frame.data_mut(0)[row * stride..row * stride + row_bytes].copy_from_slice(pixels);
+    }
+    frame
+}
+
+/// Render `frames` frames starting at `start`, or until the first video ends,
+/// at `size` to the video file `output`, or as YUV4MPEG2 to a .y4m file or
+/// stdout for `-`.
+pub fn run(
+    renderer: &mut Renderer,
+    output: &str,
+    start: usize,
+    frames: Option<usize>,
+    size: (u32, u32),
+) {
+    // YUV 4:2:0 needs even dimensions.
+    let size = (size.0 & !1, size.1 & !1);
+    if start > 0 {
+        renderer.seek(start);
+    }
+    renderer.update();
+
+    let y4m = output == "-" || output.to_ascii_lowercase().ends_with(".y4m");
+    let mut writer = if y4m {
+        let header = y4m::Header::new(size, renderer.frame_rate(), Pixel::YUV420P);
+        Output::Y4m(Y4mWriter::create(output, &header))
+    } else {
+        Output::Encoded(VideoWriter::new(output, size, renderer.frame_rate()))
+    };
+    let mut yuv = Converter::new(Pixel::YUV420P, None);
+    let mut rendered = 0;
+    loop {
+        writer.write(yuv.convert(&rgba_frame(&renderer.capture(size), size)));
+        rendered += 1;
+        if matches!(frames, Some(frames) if rendered >= frames) || !renderer.step() {
+            break;
+        }
+        renderer.update();
+    }
+    writer.finish();
+    // stdout may be the output.
+    eprintln!("rendered {} frames to {}", rendered, output);
+}
@@ -0,0 +1,1256 @@
+use wgpu::util::DeviceExt;
+
+use crate::bitrate::Bitrates;
+use crate::differences::Differences;
+use crate::info::StreamInfo;
+use crate::overlay::Overlay;
+use crate::readout::{MetricsReadout, GRAPH_HEIGHT};
+use crate::source::{self, FrameSource, InputOptions};
+use crate::subtitles::Subtitles;
+use crate::waveform::Waveforms;
+
+/// How the videos are arranged in the target.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// all videos next to each other
+    SideBySide,
+    /// the reference on the left half and the video after it on the right
+    /// half, both over the whole target
+    Wipe,
+    /// the difference between the reference and the video after it,
+    /// amplified
+    Diff,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Vertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+}
+
+impl Vertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+            ],
+        }
+    }
+}
+
+const VERTICES: &[Vertex] = &[
+    Vertex {
+        position: [-1.0, 1.0, 0.0],
+        tex_coords: [0.0, 0.0],
+    }, // TOP LEFT
+    Vertex {
+        position: [1.0, 1.0, 0.0],
+        tex_coords: [1.0, 0.0],
+    }, // TOP RIGHT
+    Vertex {
+        position: [1.0, -1.0, 0.0],
+        tex_coords: [1.0, 1.0],
+    }, // BOTTOM RIGHT
+    Vertex {
+        position: [-1.0, -1.0, 0.0],
+        tex_coords: [0.0, 1.0],
+    }, // BOTTOM LEFT
+];
+
+const INDICES: &[u16] = &[2, 1, 0, 3, 2, 0];
+
+struct Instance {
+    transform: glam::Mat4,
+    texture: crate::texture::VideoTexture,
+    texture_bind_group: wgpu::BindGroup,
+}
+
+impl Instance {
+    fn to_raw(&self) -> InstanceRaw {
+        InstanceRaw {
+            model: self.transform.to_cols_array_2d(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct InstanceRaw {
+    model: [[f32; 4]; 4],
+}
+
+impl InstanceRaw {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            // We need to switch from using a step mode of Vertex to Instance
+            // This means that our shaders will only change to use the next
+            // instance when the shader starts processing a new instance
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    // While our vertex shader only uses locations 0, and 1 now, in later tutorials we'll
+                    // be using 2, 3, and 4, for Vertex. We'll start at slot 5 not conflict with them later
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                // A mat4 takes up 4 vertex slots as it is technically 4 vec4s. We need to define a slot
+                // for each vec4. We'll have to reassemble the mat4 in
+                // the shader.
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+/// A video to compare and its label.
+pub type Input = (String, Box<dyn FrameSource>);
+
+/// Draws the videos side by side with the overlays into any texture of the
+/// format it was created for, with or without a window.
+pub struct Renderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    format: wgpu::TextureFormat,
+    /// Size of the target the layout is made for.
+    size: (u32, u32),
+    render_pipeline: wgpu::RenderPipeline,
+    /// Draws the difference of the textures bound to groups 0 and 1.
+    diff_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+    instances: Vec<Instance>,
+    instance_buffer: wgpu::Buffer,
+    /// Transform filling the target, for a video shown on its own.
+    solo_buffer: wgpu::Buffer,
+    solo_transform: glam::Mat4,
+    solo: Option<usize>,
+    layout: Layout,
+    bind_group_layout: wgpu::BindGroupLayout,
+    overlay: Overlay,
+    readout: MetricsReadout,
+    labels: Vec<String>,
+    input_options: InputOptions,
+    differences: Option<Differences>,
+    waveforms: Option<Waveforms>,
+    bitrates: Option<Bitrates>,
+    /// Subtitles of every video, empty unless shown.
+    subtitles: Vec<Subtitles>,
+    /// Whether the info panel is shown over every video.
+    info: bool,
+}
+
+/// Options for a [`Renderer`], created with [`Renderer::builder`].
+///
+/// ```no_run
+/// # async fn example() {
+/// let mut renderer = quick_compare::Renderer::builder()
+///     .video("reference.mp4")
+///     .video("encoded.mp4")
+///     .size(1280, 720)
+///     .build_headless()
+///     .await;
+/// let rgba = renderer.capture((1280, 720));
+/// # }
+/// ```
+pub struct RendererBuilder {
+    /// Labels and sources, paths to open with `input_options` if not given.
+    inputs: Vec<(String, Option<Box<dyn FrameSource>>)>,
+    input_options: InputOptions,
+    reference: usize,
+    size: (u32, u32),
+    force_fallback_adapter: bool,
+    layout: Layout,
+    differences: Option<(f64, usize)>,
+    waveforms: bool,
+    bitrates: bool,
+    subtitles: bool,
+}
+
+impl RendererBuilder {
+    /// Add a video to compare, shown to the right of the ones added before.
+    pub fn video(mut self, path: impl Into<String>) -> Self {
+        self.inputs.push((path.into(), None));
+        self
+    }
+
+    pub fn videos<I: IntoIterator<Item = String>>(self, paths: I) -> Self {
+        paths.into_iter().fold(self, RendererBuilder::video)
+    }
+
+    /// Add frames from any `source` to compare, labelled `label`.
+    pub fn source(mut self, label: impl Into<String>, source: Box<dyn FrameSource>) -> Self {
+        self.inputs.push((label.into(), Some(source)));
+        self
+    }
+
+    /// How to read inputs without a header, such as raw .yuv files.
+    pub fn input_options(mut self, options: InputOptions) -> Self {
+        self.input_options = options;
+        self
+    }
+
+    fn open_inputs(&mut self) -> Vec<Input> {
+        let options = &self.input_options;
+        self.inputs
+            .drain(..)
+            .map(|(label, source)| {
+                let source = source.unwrap_or_else(|| source::open(&label, options));
+                (label, source)
+            })
+            .collect()
+    }
+
+    /// Index of the video the metrics are measured against, 0 by default.
+    pub fn reference(mut self, index: usize) -> Self {
+        self.reference = index;
+        self
+    }
+
+    /// Size of the target the layout is made for, 1280x720 by default.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = (width, height);
+        self
+    }
+
+    /// Render in software even when there is a GPU. Only used by
+    /// [`RendererBuilder::build_headless`].
+    pub fn force_fallback_adapter(mut self, force_fallback_adapter: bool) -> Self {
+        self.force_fallback_adapter = force_fallback_adapter;
+        self
+    }
+
+    /// How to arrange the videos, see [`Renderer::set_layout`].
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Scan for differences in the background, see [`Renderer::find_differences`].
+    pub fn find_differences(mut self, threshold: f64, worst: usize) -> Self {
+        self.differences = Some((threshold, worst));
+        self
+    }
+
+    /// Analyse the audio of the videos in the background, see
+    /// [`Renderer::show_waveforms`].
+    pub fn waveforms(mut self, waveforms: bool) -> Self {
+        self.waveforms = waveforms;
+        self
+    }
+
+    /// Show the bitrate of the videos over time, see
+    /// [`Renderer::show_bitrates`].
+    pub fn bitrates(mut self, bitrates: bool) -> Self {
+        self.bitrates = bitrates;
+        self
+    }
+
+    /// Overlay the subtitles of the videos, see [`Renderer::show_subtitles`].
+    pub fn subtitles(mut self, subtitles: bool) -> Self {
+        self.subtitles = subtitles;
+        self
+    }
+
+    /// Build a renderer with its own device for offscreen targets.
+    pub async fn build_headless(mut self) -> Renderer {
+        let mut renderer = Renderer::headless(
+            self.open_inputs(),
+            self.reference,
+            self.input_options.clone(),
+            self.size,
+            self.force_fallback_adapter,
+        )
+        .await;
+        renderer.set_layout(self.layout);
+        if let Some((threshold, worst)) = self.differences {
+            renderer.find_differences(threshold, worst);
+        }
+        if self.waveforms {
+            renderer.show_waveforms();
+        }
+        if self.bitrates {
+            renderer.show_bitrates();
+        }
+        if self.subtitles {
+            renderer.show_subtitles();
+        }
+        renderer
+    }
+
+    /// Build a renderer drawing into targets of `format` with an existing
+    /// device, e.g. to embed the comparison in another wgpu application.
+    pub fn build(
+        mut self,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        format: wgpu::TextureFormat,
+    ) -> Renderer {
+        let mut renderer = Renderer::new(
+            device,
+            queue,
+            format,
+            self.size,
+            self.open_inputs(),
+            self.reference,
+            self.input_options.clone(),
+        );
+        renderer.set_layout(self.layout);
+        if let Some((threshold, worst)) = self.differences {
+            renderer.find_differences(threshold, worst);
+        }
+        if self.waveforms {
+            renderer.show_waveforms();
+        }
+        if self.bitrates {
+            renderer.show_bitrates();
+        }
+        if self.subtitles {
+            renderer.show_subtitles();
+        }
+        renderer
+    }
+}
+
+impl Renderer {
+    pub fn builder() -> RendererBuilder {
+        RendererBuilder {
+            inputs: Vec::new(),
+            input_options: InputOptions::default(),
+            reference: 0,
+            size: (1280, 720),
+            force_fallback_adapter: false,
+            layout: Layout::SideBySide,
+            differences: None,
+            waveforms: false,
+            bitrates: false,
+            subtitles: false,
+        }
+    }
+
+    /// Renderer for an offscreen target of `size`, without a window. With
+    /// `force_fallback_adapter`, or when there is no GPU, it renders in
+    /// software.
+    pub async fn headless(
+        inputs: Vec<Input>,
+        reference: usize,
+        input_options: InputOptions,
+        size: (u32, u32),
+        force_fallback_adapter: bool,
+    ) -> Self {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        // Only try the fallback adapter once when it is forced.
+        let fallbacks: &[bool] = if force_fallback_adapter {
+            &[true]
+        } else {
+            &[false, true]
+        };
+        let mut adapter = None;
+        for &fallback in fallbacks {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: None,
+                    force_fallback_adapter: fallback,
+                })
+                .await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter.expect("created an adapter");
+        let (device, queue) = request_device(&adapter).await;
+
+        Self::new(
+            device,
+            queue,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            size,
+            inputs,
+            reference,
+            input_options,
+        )
+    }
+
+    /// Renderer drawing into targets of `format` and `size` with `device`.
+    /// `input_options` are used to open the inputs again for the difference
+    /// scan.
+    pub fn new(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        format: wgpu::TextureFormat,
+        size: (u32, u32),
+        inputs: Vec<Input>,
+        reference: usize,
+        input_options: InputOptions,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    // This should match the filterable field of the
+                    // corresponding Texture entry above.
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(64),
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        });
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let create_pipeline = |layout: &wgpu::PipelineLayout, fragment_entry_point: &str| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(fragment_entry_point),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[Vertex::desc(), InstanceRaw::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: fragment_entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    unclipped_depth: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+        let render_pipeline = create_pipeline(&render_pipeline_layout, "fs_main");
+        let diff_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Diff pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let diff_pipeline = create_pipeline(&diff_pipeline_layout, "fs_diff");
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index buffer"),
+            contents: bytemuck::cast_slice(INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let num_indices = INDICES.len() as u32;
+
+        let transforms = side_by_side(inputs.len(), size);
+        let (labels, sources): (Vec<_>, Vec<_>) = inputs.into_iter().unzip();
+        let instances = sources
+            .into_iter()
+            .zip(&labels)
+            .zip(transforms)
+            .enumerate()
+            .map(|(x, ((source, label), transform))| {
+                let texture =
+                    crate::texture::VideoTexture::from_source(source, &device, &queue, Some(label));
+                let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&texture.texture.view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&texture.texture.sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: device
+                                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                                    label: Some("Uniform Buffer"),
+                                    contents: bytemuck::cast_slice(&[[0.0; 4]; 4]),
+                                    usage: wgpu::BufferUsages::UNIFORM
+                                        | wgpu::BufferUsages::COPY_DST,
+                                })
+                                .as_entire_binding(),
+                        },
+                    ],
+                    label: Some(&format!("texture bind group {}", x)),
+                });
+
+                Instance {
+                    transform,
+                    texture,
+                    texture_bind_group,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Instance Buffer"),
+            contents: bytemuck::cast_slice(&instance_data),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let solo_transform = generate_matrix(size.0, size.1);
+        let solo_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Solo Instance Buffer"),
+            contents: bytemuck::cast_slice(&[InstanceRaw {
+                model: solo_transform.to_cols_array_2d(),
+            }]),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let overlay = Overlay::new(&device, format);
+        let mut readout = MetricsReadout::new(instances.len(), reference);
+        readout.update(
+            &instances
+                .iter()
+                .map(|instance| &instance.texture)
+                .collect::<Vec<_>>(),
+        );
+
+        Self {
+            device,
+            queue,
+            format,
+            size,
+            render_pipeline,
+            diff_pipeline,
+            vertex_buffer,
+            index_buffer,
+            num_indices,
+            instances,
+            instance_buffer,
+            solo_buffer,
+            solo_transform,
+            solo: None,
+            layout: Layout::SideBySide,
+            bind_group_layout,
+            overlay,
+            readout,
+            labels,
+            input_options,
+            differences: None,
+            waveforms: None,
+            bitrates: None,
+            subtitles: Vec::new(),
+            info: false,
+        }
+    }
+
+    /// Device the renderer draws with.
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    /// Lay out for a target of `size`.
+    pub fn resize(&mut self, size: (u32, u32)) {
+        self.size = size;
+        self.lay_out(size);
+    }
+
+    /// Place the videos for a target of `size`, from the next draw on.
+    fn lay_out(&mut self, size: (u32, u32)) {
+        let transforms = side_by_side(self.instances.len(), size);
+        for (instance, transform) in self.instances.iter_mut().zip(transforms) {
+            instance.transform = transform;
+        }
+        let instance_data = self
+            .instances
+            .iter()
+            .map(Instance::to_raw)
+            .collect::<Vec<_>>();
+        self.queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&instance_data),
+        );
+        self.solo_transform = generate_matrix(size.0, size.1);
+        self.queue.write_buffer(
+            &self.solo_buffer,
+            0,
+            bytemuck::cast_slice(&[InstanceRaw {
+                model: self.solo_transform.to_cols_array_2d(),
+            }]),
+        );
+    }
+
+    /// Bind the current frame of every video for drawing.
+    pub fn update(&mut self) {
+        self.instances.iter_mut().for_each(|instance| {
+            let mx_total = generate_matrix(self.size.0, self.size.1);
+            let mx_ref: &[f32; 16] = mx_total.as_ref();
+            let transform_matrix =
+                self.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Uniform Buffer"),
+                        contents: bytemuck::cast_slice(mx_ref),
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    });
+            instance.texture_bind_group =
+                self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(
+                                &instance.texture.texture.view,
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(
+                                &instance.texture.texture.sampler,
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: transform_matrix.as_entire_binding(),
+                        },
+                    ],
+                    label: Some("texture bind group"),
+                });
+        })
+    }
+
+    /// Advance the videos whose next frame is due during playback.
+    pub fn get_next_frame(&mut self) {
+        let mut updated = false;
+        self.instances.iter_mut().for_each(|instance| {
+            updated |= instance.texture.get_next_frame(&self.device, &self.queue);
+        });
+        if updated {
+            self.update_metrics();
+        }
+    }
+
+    /// Advance every video by exactly one frame, as when rendering to a file.
+    /// Returns false once any of them has ended, or right away when all of
+    /// them are stills.
+    pub fn step(&mut self) -> bool {
+        let mut advanced = true;
+        self.instances.iter_mut().for_each(|instance| {
+            advanced &= instance.texture.next_frame(&self.device, &self.queue);
+        });
+        self.update_metrics();
+        // Stills never end by themselves.
+        advanced
+            && !self
+                .instances
+                .iter()
+                .all(|instance| instance.texture.source.is_still())
+    }
+
+    /// Show frame `index` of every video.
+    pub fn seek(&mut self, index: usize) {
+        self.instances.iter_mut().for_each(|instance| {
+            instance.texture.seek(&self.device, &self.queue, index);
+        });
+        self.update_metrics();
+    }
+
+    /// Show only video `index` over the whole target instead of all of them
+    /// side by side.
+    pub fn set_solo(&mut self, index: Option<usize>) {
+        self.solo = index.filter(|&index| index < self.instances.len());
+    }
+
+    /// Arrange the videos by `layout`. The wipe and diff layouts show the
+    /// reference and the video after it, side by side is used for a single
+    /// video.
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    /// The reference and the video after it when they are shown over the
+    /// whole target by the layout, `None` when showing a single video or
+    /// all of them side by side.
+    fn pair(&self) -> Option<(usize, usize)> {
+        if self.solo.is_some() || self.layout == Layout::SideBySide || self.instances.len() < 2 {
+            return None;
+        }
+        let reference = self.readout.reference();
+        Some((reference, (reference + 1) % self.instances.len()))
+    }
+
+    /// Indices of the videos shown, for the overlays over them.
+    fn shown(&self) -> std::ops::Range<usize> {
+        match (self.solo, self.pair()) {
+            (Some(index), _) | (None, Some((_, index))) => index..index + 1,
+            (None, None) => 0..self.instances.len(),
+        }
+    }
+
+    /// Measure against the next video instead of the current reference.
+    pub fn next_reference(&mut self) {
+        self.readout.next_reference();
+        self.update_metrics();
+    }
+
+    /// Scan the reference and the video after it for differences in the
+    /// background, see [`Differences::spawn`].
+    pub fn find_differences(&mut self, threshold: f64, worst: usize) {
+        if self.labels.len() < 2 {
+            return;
+        }
+        let reference = self.readout.reference();
+        let distorted = (reference + 1) % self.labels.len();
+        self.differences = Some(Differences::spawn(
+            self.labels[reference].clone(),
+            self.labels[distorted].clone(),
+            self.input_options.clone(),
+            threshold,
+            worst,
+        ));
+    }
+
+    /// Analyse the audio of every video in the background and show its
+    /// waveform, loudness, true peak and offset against the reference above
+    /// the metrics graph.
+    pub fn show_waveforms(&mut self) {
+        self.waveforms = Some(Waveforms::spawn(&self.labels));
+    }
+
+    /// Read the size and picture type of every frame of the videos in the
+    /// background and show their bitrate around the frame shown, coloured
+    /// by picture type, above the metrics graph.
+    pub fn show_bitrates(&mut self) {
+        let inputs = (0..self.instances.len())
+            .map(|index| self.bitrate_input(index))
+            .collect::<Vec<_>>();
+        self.bitrates = Some(Bitrates::spawn(&inputs));
+    }
+
+    /// Path and stream of video `index` to read the frames of again for the
+    /// bitrate, `None` for sources that can't be read twice such as pipes
+    /// and live streams, or that aren't read with ffmpeg.
+    fn bitrate_input(&self, index: usize) -> Option<(String, usize)> {
+        let source = &self.instances[index].texture.source;
+        let label = &self.labels[index];
+        if source.media_info().is_none() || !source::can_reopen(label) {
+            return None;
+        }
+        let stream = source
+            .streams()
+            .into_iter()
+            .find(|stream| stream.selected)?;
+        Some((label.clone(), stream.index))
+    }
+
+    /// Decode the subtitles of every video in the background and show them
+    /// over it at the time of its frame, see [`Subtitles`].
+    pub fn show_subtitles(&mut self) {
+        self.subtitles = self
+            .labels
+            .iter()
+            .map(|label| Subtitles::spawn(label))
+            .collect();
+    }
+
+    /// Show or hide the panel with the container, codec, colour and streams
+    /// of every video and the type of the frame shown over it.
+    pub fn toggle_info(&mut self) {
+        self.info = !self.info;
+    }
+
+    /// Switch video `index` to the next video stream of its file and return
+    /// it, `None` if there is no other one or the video can't switch.
+    pub fn next_stream(&mut self, index: usize) -> Option<StreamInfo> {
+        let instance = self.instances.get_mut(index)?;
+        let videos = instance
+            .texture
+            .source
+            .streams()
+            .into_iter()
+            .filter(|stream| stream.medium == ffmpeg_next::media::Type::Video)
+            .collect::<Vec<_>>();
+        let current = videos.iter().position(|stream| stream.selected)?;
+        let next = &videos[(current + 1) % videos.len()];
+        if next.selected
+            || !instance
+                .texture
+                .select_stream(&self.device, &self.queue, next.index)
+        {
+            return None;
+        }
+        self.readout.input_changed(index);
+        self.update_metrics();
+        let input = self.bitrate_input(index);
+        if let Some(bitrates) = &mut self.bitrates {
+            bitrates.input_changed(index, input);
+        }
+        Some(StreamInfo {
+            selected: true,
+            ..next.clone()
+        })
+    }
+
+    /// Video drawn under `position` in a target of `size`.
+    pub fn video_at(&self, position: (f64, f64), size: (u32, u32)) -> Option<usize> {
+        if self.solo.is_some() {
+            return self.solo;
+        }
+        if let Some((reference, other)) = self.pair() {
+            let left = self.layout == Layout::Wipe && position.0 < size.0 as f64 / 2.0;
+            return Some(if left { reference } else { other });
+        }
+        let (x, y) = (position.0 as f32, position.1 as f32);
+        (0..self.instances.len()).find(|&index| {
+            let (left, top, width, height) = self.placement(index, size);
+            x >= left && x < left + width && y >= top && y < top + height
+        })
+    }
+
+    /// Seek to the next different frame, returning whether there was one.
+    pub fn next_difference(&mut self) -> bool {
+        let current_frame = self.current_frame();
+        let frame = self
+            .differences
+            .as_ref()
+            .and_then(|differences| differences.next(current_frame));
+        self.seek_to(frame)
+    }
+
+    pub fn previous_difference(&mut self) -> bool {
+        let current_frame = self.current_frame();
+        let frame = self
+            .differences
+            .as_ref()
+            .and_then(|differences| differences.previous(current_frame));
+        self.seek_to(frame)
+    }
+
+    pub fn next_worst_difference(&mut self) -> bool {
+        let frame = self.differences.as_mut().and_then(Differences::next_worst);
+        self.seek_to(frame)
+    }
+
+    /// Switch the metric shown in the graph between PSNR and SSIM.
+    pub fn toggle_graph_metric(&mut self) {
+        self.readout.toggle_graph_metric();
+    }
+
+    /// Frame of the metrics graph under `position` in a target of `size`.
+    pub fn frame_at(&self, position: (f64, f64), size: (u32, u32)) -> Option<usize> {
+        self.readout.frame_at(position, size)
+    }
+
+    /// Index of the video the metrics are measured against.
+    pub fn reference(&self) -> usize {
+        self.readout.reference()
+    }
+
+    /// Frame index of the reference video.
+    pub fn current_frame(&self) -> usize {
+        self.instances
+            .get(self.readout.reference())
+            .map_or(0, |instance| instance.texture.source.frame_index())
+    }
+
+    /// Frame rate of the reference video.
+    pub fn frame_rate(&self) -> ffmpeg_next::Rational {
+        self.instances
+            .get(self.readout.reference())
+            .map_or(ffmpeg_next::Rational::new(30, 1), |instance| {
+                instance.texture.source.frame_rate()
+            })
+    }
+
+    /// Path or label of every video.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Time in seconds of the frame shown of video `index` and how long it
+    /// is shown during playback.
+    pub fn frame_time(&self, index: usize) -> Option<(f64, f64)> {
+        self.instances.get(index).map(|instance| {
+            let source = &instance.texture.source;
+            (source.timestamp(), 1.0 / f64::from(source.frame_rate()))
+        })
+    }
+
+    /// Where video `index` is drawn in a target of `size`, as left, top,
+    /// width and height in pixels.
+    fn placement(&self, index: usize, size: (u32, u32)) -> (f32, f32, f32, f32) {
+        let transform = if self.solo.is_some() || self.pair().is_some() {
+            self.solo_transform
+        } else {
+            self.instances[index].transform
+        };
+        let top_left = transform.transform_point3(glam::Vec3::new(-1.0, 1.0, 0.0));
+        let bottom_right = transform.transform_point3(glam::Vec3::new(1.0, -1.0, 0.0));
+        let pixel = |point: glam::Vec3| {
+            (
+                (point.x + 1.0) / 2.0 * size.0 as f32,
+                (1.0 - point.y) / 2.0 * size.1 as f32,
+            )
+        };
+        let (left, top) = pixel(top_left);
+        let (right, bottom) = pixel(bottom_right);
+        (left, top, right - left, bottom - top)
+    }
+
+    fn seek_to(&mut self, frame: Option<usize>) -> bool {
+        match frame {
+            Some(frame) => {
+                self.seek(frame);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn update_metrics(&mut self) {
+        let textures = self
+            .instances
+            .iter()
+            .map(|instance| &instance.texture)
+            .collect::<Vec<_>>();
+        self.readout.update(&textures);
+    }
+
+    pub fn get_previous_frame(&mut self) {
+        self.instances.iter_mut().for_each(|instance| {
+            instance
+                .texture
+                .get_previous_frame(&self.device, &self.queue);
+        });
+    }
+
+    /// Render the composite at `size`, or the size of the target, to a PNG
+    /// next to the first video and return its path.
+    pub fn screenshot(&mut self, size: Option<(u32, u32)>) -> std::io::Result<std::path::PathBuf> {
+        let size = size.unwrap_or(self.size);
+        let pixels = self.capture(size);
+        let path = crate::capture::output_path(&self.labels[0], self.current_frame(), ".png");
+        crate::capture::write_png(&path, size, &pixels)?;
+        Ok(path)
+    }
+
+    /// Render the composite offscreen at `size` and return it as RGBA rows.
+    pub fn capture(&mut self, size: (u32, u32)) -> Vec<u8> {
+        let textures = self
+            .instances
+            .iter()
+            .map(|instance| &instance.texture)
+            .collect::<Vec<_>>();
+        self.readout.wait(&textures);
+        let target = crate::capture::create_target(&self.device, self.format, size);
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        if size != self.size {
+            self.lay_out(size);
+        }
+        self.draw(&view, size);
+        if size != self.size {
+            self.lay_out(self.size);
+        }
+        crate::capture::read_texture(&self.device, &self.queue, &target, self.format, size)
+    }
+
+    /// Write the frame currently shown of every video at its native size
+    /// next to the video, see [`crate::capture::write_frame_png`], and
+    /// return where each went.
+    pub fn export_frames(&self) -> Vec<std::io::Result<std::path::PathBuf>> {
+        self.instances
+            .iter()
+            .zip(&self.labels)
+            .map(|(instance, label)| {
+                let path = crate::capture::frame_path(label, instance.texture.source.frame_index());
+                crate::capture::write_frame_png(&path, &instance.texture.frame)?;
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// Labels of the videos, with the latency of live streams.
+    fn status_labels(&self) -> Vec<String> {
+        self.instances
+            .iter()
+            .zip(&self.labels)
+            .map(
+                |(instance, label)| match instance.texture.source.live_status() {
+                    Some(status) => format!("{}  {}", label, status),
+                    None => label.clone(),
+                },
+            )
+            .collect()
+    }
+
+    /// Draw all videos and overlays into `view`, a target of `size` in the
+    /// format of the renderer.
+    pub fn draw(&mut self, view: &wgpu::TextureView, size: (u32, u32)) {
+        // Frames skipped while the metrics were busy get measured now.
+        self.update_metrics();
+        self.overlay.clear();
+        for index in self.shown().filter(|&index| index < self.subtitles.len()) {
+            let source = &self.instances[index].texture.source;
+            let (dimensions, time) = (source.dimensions(), source.timestamp());
+            let placement = self.placement(index, size);
+            self.subtitles[index].draw(&mut self.overlay, placement, dimensions, time);
+        }
+        if self.info {
+            for index in self.shown() {
+                let texture = &self.instances[index].texture;
+                let mut lines = match texture.source.media_info() {
+                    Some(media_info) => media_info.lines(),
+                    None => {
+                        let (width, height) = texture.source.dimensions();
+                        vec![format!(
+                            "{}x{}  {:?}  {:.3} fps",
+                            width,
+                            height,
+                            texture.source.format(),
+                            f64::from(texture.source.frame_rate())
+                        )]
+                    }
+                };
+                lines.push(crate::info::frame_line(&texture.frame));
+                lines.extend(texture.source.streams().iter().map(StreamInfo::to_string));
+                let placement = self.placement(index, size);
+                crate::info::draw(&mut self.overlay, placement, &lines);
+            }
+        }
+        let labels = self.status_labels();
+        match self.solo {
+            Some(index) => self
+                .readout
+                .draw_single(&mut self.overlay, index, &labels[index]),
+            None => self.readout.draw(&mut self.overlay, size, &labels),
+        }
+        if let Some(differences) = &self.differences {
+            let current_frame = self.current_frame();
+            differences.draw(&mut self.overlay, size, current_frame);
+        }
+        if self.solo.is_none() {
+            let graph = if self.instances.len() > 1 {
+                GRAPH_HEIGHT
+            } else {
+                0.0
+            };
+            let mut bottom = size.1 as f32 - graph;
+            if let Some(waveforms) = &self.waveforms {
+                let times = self
+                    .instances
+                    .iter()
+                    .map(|instance| instance.texture.source.timestamp())
+                    .collect::<Vec<_>>();
+                let reference = self.readout.reference();
+                bottom = waveforms.draw(&mut self.overlay, size, bottom, &times, reference);
+            }
+            if let Some(bitrates) = &self.bitrates {
+                let frames = self
+                    .instances
+                    .iter()
+                    .map(|instance| instance.texture.source.frame_index())
+                    .collect::<Vec<_>>();
+                bitrates.draw(&mut self.overlay, size, bottom, &frames);
+            }
+        }
+        self.overlay.prepare(&self.device, size);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.0,
+                            g: 0.0,
+                            b: 0.0,
+                            a: 1.0,
+                        }),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+
+            match (self.solo, self.pair()) {
+                (Some(index), _) => {
+                    render_pass.set_vertex_buffer(1, self.solo_buffer.slice(..));
+                    render_pass.set_bind_group(0, &self.instances[index].texture_bind_group, &[]);
+                    render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+                }
+                (None, Some((reference, other))) => {
+                    render_pass.set_vertex_buffer(1, self.solo_buffer.slice(..));
+                    let reference = &self.instances[reference].texture_bind_group;
+                    let other = &self.instances[other].texture_bind_group;
+                    if self.layout == Layout::Diff {
+                        render_pass.set_pipeline(&self.diff_pipeline);
+                        render_pass.set_bind_group(0, reference, &[]);
+                        render_pass.set_bind_group(1, other, &[]);
+                        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+                    } else {
+                        render_pass.set_bind_group(0, reference, &[]);
+                        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+                        // The other video only over the right half.
+                        let half = size.0 / 2;
+                        render_pass.set_scissor_rect(half, 0, size.0 - half, size.1);
+                        render_pass.set_bind_group(0, other, &[]);
+                        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+                        render_pass.set_scissor_rect(0, 0, size.0, size.1);
+                    }
+                }
+                (None, None) => {
+                    for (index, instance) in self.instances.iter().enumerate() {
+                        let index = index as u32;
+                        render_pass.set_bind_group(0, &instance.texture_bind_group, &[]);
+                        render_pass.draw_indexed(0..self.num_indices, 0, index..(index + 1) as _);
+                    }
+                }
+            }
+
+            self.overlay.draw(&mut render_pass);
+        }
+
+        // submit will accept anything that implements IntoIter
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+/// Device and queue for rendering with `adapter`.
+pub async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits: if cfg!(target_arch = "wasm32") {
+                    wgpu::Limits::downlevel_webgl2_defaults()
+                } else {
+                    wgpu::Limits::default()
+                },
+                label: None,
+            },
+            None,
+        )
+        .await
+        .expect("created device and queue")
+}
+
+/// Transforms placing `count` videos next to each other in a target of
+/// `size`.
+fn side_by_side(count: usize, size: (u32, u32)) -> Vec<glam::Mat4> {
+    let aspect_ratio = size.1 as f32 / size.0 as f32;
+    (0..count)
+        .map(|x| {
+            let position = glam::Mat4::from_translation(glam::Vec3 {
+                x: aspect_ratio,
+                y: 0.0,
+                z: 0.0,
+            }) * glam::Mat4::from_translation(glam::Vec3 {
+                // A single video stays in the middle.
+                x: (2.0 * (x as f32 / (count.max(2) - 1) as f32)),
+                y: 0.0,
+                z: 0.0,
+            }) * glam::Mat4::from_translation(glam::Vec3 {
+                x: -aspect_ratio,
+                y: 0.0,
+                z: 0.0,
+            });
+            let scale = glam::Mat4::from_scale(glam::Vec3 {
+                x: 1.0 / count as f32,
+                y: 1.0 / count as f32,
+                z: 1.0,
+            });
+            position * scale * generate_matrix(size.0, size.1)
+        })
+        .collect()
+}
+
+fn generate_matrix(width: u32, height: u32) -> glam::Mat4 {
+    let width = width as f32;
+    let height = height as f32;
+
+    glam::Mat4::from_scale(glam::Vec3 {
+        x: 1.0,
+        y: width / height,
+        z: 1.0,
+    })
+}
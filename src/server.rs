@@ -0,0 +1,223 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use tungstenite::{Message, WebSocket};
+
+/// The page in client/. The server tells it which role to take when it
+/// connects back over a WebSocket.
+const FILES: &[(&str, &str, &str)] = &[
+    ("/", "text/html", include_str!("../client/index.html")),
+    (
+        "/app.js",
+        "text/javascript",
+        include_str!("../client/app.js"),
+    ),
+];
+
+/// What the page does once connected.
+#[derive(Clone, Copy)]
+pub enum Role {
+    /// Send the camera of the browser, see [`crate::browser`].
+    Camera,
+    /// Show the comparison and control playback, see [`crate::remote`].
+    Viewer,
+}
+
+impl Role {
+    fn name(self) -> &'static str {
+        match self {
+            Role::Camera => "camera",
+            Role::Viewer => "viewer",
+        }
+    }
+}
+
+/// Serve the client page at `address` in the background, telling it to take
+/// `role` and handing every WebSocket it opens to `connected`, each on its own
+/// thread. Fails if `address` can't be listened at.
+pub fn spawn<F>(address: &str, role: Role, connected: F) -> std::io::Result<()>
+where
+    F: Fn(WebSocket<TcpStream>) + Clone + Send + 'static,
+{
+    let listener = TcpListener::bind(address)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let connected = connected.clone();
+            std::thread::spawn(move || handle(stream, role, connected));
+        }
+    });
+    Ok(())
+}
+
+/// Longest request line and headers read, longer requests are dropped.
+const MAX_HEAD: usize = 8192;
+
+/// The request line and headers of an HTTP request.
+struct Request {
+    path: String,
+    /// Names in lower case with their values.
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn is_websocket(&self) -> bool {
+        matches!(self.header("upgrade"), Some(upgrade) if upgrade.eq_ignore_ascii_case("websocket"))
+    }
+
+    /// Whether it comes from the page served here, or from something other
+    /// than a browser, which sends no Origin. Pages of other sites could
+    /// otherwise connect through the browser of whoever opens them.
+    fn is_same_origin(&self) -> bool {
+        let origin = match self.header("origin") {
+            Some(origin) => origin,
+            None => return true,
+        };
+        let host = origin.split_once("://").map(|(_, host)| host);
+        matches!((host, self.header("host")), (Some(origin), Some(host)) if origin.eq_ignore_ascii_case(host))
+    }
+}
+
+/// Read the request line and headers, `None` if the client sends something
+/// else or stops before they end.
+fn read_request(reader: &mut impl BufRead) -> Option<Request> {
+    let mut read = 0;
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        let length = Read::take(&mut *reader, (MAX_HEAD - read) as u64)
+            .read_line(&mut line)
+            .ok()?;
+        if !line.ends_with('\n') {
+            return None;
+        }
+        read += length;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line.to_string());
+    }
+
+    let mut lines = lines.into_iter();
+    let request = lines.next()?;
+    let path = request.split(' ').nth(1)?;
+    let path = path.split('?').next().unwrap_or(path).to_string();
+    let headers = lines
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        })
+        .collect();
+    Some(Request { path, headers })
+}
+
+fn handle(stream: TcpStream, role: Role, connected: impl Fn(WebSocket<TcpStream>)) {
+    let mut reader = BufReader::new(&stream);
+    let request = match read_request(&mut reader) {
+        Some(request) => request,
+        None => return,
+    };
+    // Whatever came after the headers already belongs to the WebSocket.
+    let rest = reader.buffer().to_vec();
+
+    if !request.is_same_origin() {
+        respond(&stream, "403 Forbidden", None);
+        return;
+    }
+    if !request.is_websocket() {
+        serve(&stream, &request.path);
+        return;
+    }
+    let key = match request.header("sec-websocket-key") {
+        Some(key) if request.header("sec-websocket-version") == Some("13") => key,
+        _ => {
+            respond(&stream, "400 Bad Request", None);
+            return;
+        }
+    };
+    let handshake = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        tungstenite::handshake::derive_accept_key(key.as_bytes())
+    );
+    if (&stream).write_all(handshake.as_bytes()).is_err() {
+        return;
+    }
+
+    let mut socket =
+        WebSocket::from_partially_read(stream, rest, tungstenite::protocol::Role::Server, None);
+    let hello = format!("{{\"role\":\"{}\"}}", role.name());
+    if socket.write_message(Message::Text(hello)).is_ok() {
+        connected(socket);
+    }
+}
+
+fn serve(stream: &TcpStream, path: &str) {
+    match FILES.iter().find(|(file, _, _)| *file == path) {
+        Some((_, content_type, body)) => respond(stream, "200 OK", Some((content_type, body))),
+        None => respond(stream, "404 Not Found", None),
+    }
+}
+
+/// Answer with `status` and the content type and body of `file`, if any.
+fn respond(mut stream: &TcpStream, status: &str, file: Option<(&str, &str)>) {
+    let (content_type, body) = file.unwrap_or(("text/plain", ""));
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    // The client may be gone already.
+    let _ = stream.write_all(response.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(head: &str) -> Option<Request> {
+        read_request(&mut head.as_bytes())
+    }
+
+    #[test]
+    fn reads_path_and_headers() {
+        let request = request(
+            "GET /app.js?v=1 HTTP/1.1\r\nHost: localhost:8080\r\nUpgrade: WebSocket\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.path, "/app.js");
+        assert_eq!(request.header("host"), Some("localhost:8080"));
+        assert!(request.is_websocket());
+    }
+
+    #[test]
+    fn incomplete_or_long_heads_are_dropped() {
+        assert!(request("GET / HTTP/1.1\r\nHost: localhost\r\n").is_none());
+        let long = format!("GET / HTTP/1.1\r\nCookie: {}\r\n\r\n", "a".repeat(MAX_HEAD));
+        assert!(request(&long).is_none());
+    }
+
+    #[test]
+    fn only_the_same_origin_is_accepted() {
+        let origin = |origin: &str| {
+            request(&format!(
+                "GET / HTTP/1.1\r\nHost: 10.0.0.2:8080\r\n{}\r\n",
+                origin
+            ))
+            .unwrap()
+            .is_same_origin()
+        };
+        assert!(origin(""));
+        assert!(origin("Origin: http://10.0.0.2:8080\r\n"));
+        assert!(!origin("Origin: https://example.com\r\n"));
+        assert!(!origin("Origin: null\r\n"));
+    }
+}
@@ -48,4 +48,17 @@ var s_diffuse: sampler;
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-}
\ No newline at end of file
+}
+
+@group(1) @binding(0)
+var t_other: texture_2d<f32>;
+@group(1) @binding(1)
+var s_other: sampler;
+
+// Difference to the texture of group 1, amplified to be seen.
+@fragment
+fn fs_diff(in: VertexOutput) -> @location(0) vec4<f32> {
+    let reference = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let other = textureSample(t_other, s_other, in.tex_coords);
+    return vec4<f32>(min(abs(reference.rgb - other.rgb) * 4.0, vec3<f32>(1.0)), 1.0);
+}
@@ -0,0 +1,257 @@
+use ffmpeg_next::{format::Pixel, software::scaling::Context, util::frame::Video, Rational};
+
+use crate::browser::{self, BrowserCamera};
+use crate::images::{self, ImageSequence};
+pub use crate::info::{MediaInfo, StreamInfo};
+use crate::live::{self, LiveStatus};
+use crate::pipe::{self, Piped};
+use crate::raw::RawVideo;
+use crate::video::{self, VideoStream};
+use crate::y4m::Y4mVideo;
+
+/// Anything that produces decoded video frames, such as [`VideoStream`].
+/// Frames may be in any pixel format, [`Converter`] brings them to the one
+/// needed.
+pub trait FrameSource {
+    /// Next frame, or `None` at the end.
+    fn next_frame(&mut self) -> Option<Video>;
+
+    /// Frame `index`, after which [`FrameSource::next_frame`] continues from
+    /// there. `None` if there is no such frame.
+    fn seek(&mut self, index: usize) -> Option<Video>;
+
+    /// Index of the frame most recently returned.
+    fn frame_index(&self) -> usize;
+
+    /// Average number of frames per second.
+    fn frame_rate(&self) -> Rational;
+
+    /// Presentation time of the frame most recently returned in seconds.
+    fn timestamp(&self) -> f64 {
+        self.frame_index() as f64 / f64::from(self.frame_rate())
+    }
+
+    /// Size of the returned frames.
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Pixel format of the returned frames.
+    fn format(&self) -> Pixel;
+
+    /// Whether every frame is the same picture and the source never ends.
+    fn is_still(&self) -> bool {
+        false
+    }
+
+    /// How a live stream is keeping up, `None` for anything else.
+    fn live_status(&self) -> Option<LiveStatus> {
+        None
+    }
+
+    /// Streams of the input file, empty for anything else.
+    fn streams(&self) -> Vec<StreamInfo> {
+        Vec::new()
+    }
+
+    /// Decode video stream `index` of the input file instead and return its
+    /// frame at the time of the one shown, `None` if the source can't switch.
+    fn select_stream(&mut self, _index: usize) -> Option<Video> {
+        None
+    }
+
+    /// Container, codec and colour of the video stream decoded, `None` for
+    /// anything but files read with ffmpeg.
+    fn media_info(&self) -> Option<MediaInfo> {
+        None
+    }
+}
+
+/// Frame rate of inputs without one of their own unless given, the same as ffmpeg uses.
+const DEFAULT_FRAME_RATE: (i32, i32) = (25, 1);
+
+/// What can't be read from inputs without a header.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct InputOptions {
+    /// size of raw .yuv inputs as WIDTHxHEIGHT
+    #[clap(long = "size", value_parser = crate::capture::parse_size)]
+    pub dimensions: Option<(u32, u32)>,
+    /// pixel format of raw .yuv inputs as named by ffmpeg, defaults to yuv420p
+    #[clap(long = "pix-fmt", value_parser = parse_pixel_format)]
+    pub pixel_format: Option<Pixel>,
+    /// frame rate of raw .yuv inputs, image sequences and browser cameras,
+    /// e.g. 30 or 30000/1001, defaults to 25
+    #[clap(long = "fps", value_parser = parse_frame_rate)]
+    pub frame_rate: Option<Rational>,
+}
+
+/// Open the input at `path`. Files with the .yuv extension are read as raw
+/// video described by `options`, .y4m files as YUV4MPEG2. Directories of
+/// images and printf patterns like `frames/%05d.png` are read as image
+/// sequences, single images as stills, and URLs of live streams are received
+/// with low latency. `webcam://HOST:PORT` serves a page there that streams
+/// the camera of the browser opening it. Anything else, including `-` for stdin, is read with
+/// ffmpeg. Stdin, named pipes and live streams only seek back as far as the
+/// last 256 MiB of frames, which are kept in memory.
+pub fn open(path: &str, options: &InputOptions) -> Box<dyn FrameSource> {
+    if live::is_live(path) {
+        return Box::new(Piped::new(Box::new(VideoStream::live(path))));
+    }
+    if let Some(address) = path.strip_prefix(browser::SCHEME) {
+        let camera = BrowserCamera::serve(address, frame_rate(options)).unwrap_or_else(|error| {
+            panic!("can't serve the camera page at {}: {}", address, error)
+        });
+        return Box::new(Piped::new(Box::new(camera)));
+    }
+    let source = open_file(path, options);
+    if pipe::is_pipe(path) {
+        return Box::new(Piped::new(source));
+    }
+    source
+}
+
+/// Whether the input at `path` can be opened again next to the source
+/// showing it, e.g. for its audio. Stdin, named pipes, live streams and
+/// browser cameras are read once, by the source.
+pub fn can_reopen(path: &str) -> bool {
+    let (path, _) = video::split_selection(path);
+    !(live::is_live(path) || pipe::is_pipe(path) || path.starts_with(browser::SCHEME))
+}
+
+fn open_file(path: &str, options: &InputOptions) -> Box<dyn FrameSource> {
+    let (path, selection) = video::split_selection(path);
+    let frame_rate = frame_rate(options);
+    let file = std::path::Path::new(path);
+    if file.is_dir() {
+        return Box::new(ImageSequence::directory(file, frame_rate));
+    }
+    if path.contains('%') && !file.exists() {
+        return Box::new(ImageSequence::pattern(path, frame_rate));
+    }
+    if images::is_image(file) {
+        return Box::new(ImageSequence::still(file, frame_rate));
+    }
+    let extension = file.extension();
+    if matches!(extension, Some(extension) if extension.eq_ignore_ascii_case("y4m")) {
+        return Box::new(Y4mVideo::open(path));
+    }
+    if matches!(extension, Some(extension) if extension.eq_ignore_ascii_case("yuv")) {
+        let dimensions = options
+            .dimensions
+            .unwrap_or_else(|| panic!("--size is required for the raw video {}", path));
+        return Box::new(RawVideo::open(
+            path,
+            options.pixel_format.unwrap_or(Pixel::YUV420P),
+            dimensions,
+            frame_rate,
+        ));
+    }
+    Box::new(VideoStream::decoded(path, &selection))
+}
+
+fn frame_rate(options: &InputOptions) -> Rational {
+    options
+        .frame_rate
+        .unwrap_or_else(|| DEFAULT_FRAME_RATE.into())
+}
+
+fn parse_pixel_format(format: &str) -> Result<Pixel, String> {
+    format
+        .parse::<Pixel>()
+        .map_err(|_| format!("unknown pixel format {}", format))
+}
+
+/// Parse a frame rate written as an integer, a fraction or a decimal number.
+fn parse_frame_rate(frame_rate: &str) -> Result<Rational, String> {
+    let invalid = || format!("invalid frame rate {}", frame_rate);
+    let (numerator, denominator) = match frame_rate.split_once('/') {
+        Some((numerator, denominator)) => (
+            numerator.parse::<i32>().map_err(|_| invalid())?,
+            denominator.parse::<i32>().map_err(|_| invalid())?,
+        ),
+        None => {
+            let frame_rate = frame_rate.parse::<f64>().map_err(|_| invalid())?;
+            ((frame_rate * 1000.0).round() as i32, 1000)
+        }
+    };
+    if numerator <= 0 || denominator <= 0 {
+        return Err(invalid());
+    }
+    Ok(Rational::new(numerator, denominator))
+}
+
+/// Converts frames to one pixel format, and size if given, whatever format
+/// and size they come in.
+pub struct Converter {
+    format: Pixel,
+    dimensions: Option<(u32, u32)>,
+    /// Scaler for the format and size of the last converted frame.
+    scaler: Option<((Pixel, u32, u32), Context)>,
+}
+
+impl Converter {
+    pub fn new(format: Pixel, dimensions: Option<(u32, u32)>) -> Self {
+        Self {
+            format,
+            dimensions,
+            scaler: None,
+        }
+    }
+
+    pub fn convert(&mut self, frame: &Video) -> Video {
+        let input = (frame.format(), frame.width(), frame.height());
+        let reuse = matches!(&self.scaler, Some((current, _)) if *current == input);
+        if !reuse {
+            let dimensions = self.dimensions.unwrap_or((input.1, input.2));
+            let scaler = Context::get(
+                input.0,
+                input.1,
+                input.2,
+                self.format,
+                dimensions.0,
+                dimensions.1,
+                ffmpeg_next::software::scaling::flag::Flags::BILINEAR,
+            )
+            .expect("scaler");
+            self.scaler = Some((input, scaler));
+        }
+
+        let mut converted = Video::empty();
+        let (_, scaler) = self.scaler.as_mut().expect("scaler");
+        scaler.run(frame, &mut converted).expect("scale frame");
+        converted
+    }
+}
+
+/// A source with every frame converted, see [`Converter`].
+pub struct Converted {
+    source: Box<dyn FrameSource>,
+    converter: Converter,
+}
+
+impl Converted {
+    pub fn new(
+        source: Box<dyn FrameSource>,
+        format: Pixel,
+        dimensions: Option<(u32, u32)>,
+    ) -> Self {
+        Self {
+            source,
+            converter: Converter::new(format, dimensions),
+        }
+    }
+
+    /// Size of the converted frames.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.converter
+            .dimensions
+            .unwrap_or_else(|| self.source.dimensions())
+    }
+
+    pub fn is_still(&self) -> bool {
+        self.source.is_still()
+    }
+
+    pub fn next_frame(&mut self) -> Option<Video> {
+        let frame = self.source.next_frame()?;
+        Some(self.converter.convert(&frame))
+    }
+}
@@ -1,114 +1,9 @@
-use wgpu::util::DeviceExt;
 use winit::window::Window;
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 3],
-    tex_coords: [f32; 2],
-}
-
-impl Vertex {
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-            ],
-        }
-    }
-}
-
-const VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [-1.0, 1.0, 0.0],
-        tex_coords: [0.0, 0.0],
-    }, // TOP LEFT
-    Vertex {
-        position: [1.0, 1.0, 0.0],
-        tex_coords: [1.0, 0.0],
-    }, // TOP RIGHT
-    Vertex {
-        position: [1.0, -1.0, 0.0],
-        tex_coords: [1.0, 1.0],
-    }, // BOTTOM RIGHT
-    Vertex {
-        position: [-1.0, -1.0, 0.0],
-        tex_coords: [0.0, 1.0],
-    }, // BOTTOM LEFT
-];
-
-const INDICES: &[u16] = &[2, 1, 0, 3, 2, 0];
-
-struct Instance {
-    transform: glam::Mat4,
-    texture: crate::texture::VideoTexture,
-    texture_bind_group: wgpu::BindGroup,
-}
-
-impl Instance {
-    fn to_raw(&self) -> InstanceRaw {
-        InstanceRaw {
-            model: self.transform.to_cols_array_2d(),
-        }
-    }
-}
-
-#[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct InstanceRaw {
-    model: [[f32; 4]; 4],
-}
-
-impl InstanceRaw {
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        use std::mem;
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
-            // We need to switch from using a step mode of Vertex to Instance
-            // This means that our shaders will only change to use the next
-            // instance when the shader starts processing a new instance
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    // While our vertex shader only uses locations 0, and 1 now, in later tutorials we'll
-                    // be using 2, 3, and 4, for Vertex. We'll start at slot 5 not conflict with them later
-                    shader_location: 5,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                // A mat4 takes up 4 vertex slots as it is technically 4 vec4s. We need to define a slot
-                // for each vec4. We'll have to reassemble the mat4 in
-                // the shader.
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-            ],
-        }
-    }
-}
+use crate::audio::{self, AudioPlayer};
+use crate::remote::Control;
+use crate::renderer::{self, Renderer};
+use crate::source::{self, InputOptions};
 
 #[derive(PartialEq, Eq)]
 pub enum VideoStatus {
@@ -116,24 +11,26 @@ pub enum VideoStatus {
     Paused,
 }
 
+/// The viewer: a [`Renderer`] presenting to the surface of a window, with
+/// playback controlled by the user.
 pub struct State {
     surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
-    instances: Vec<Instance>,
-    instance_buffer: wgpu::Buffer,
-    bind_group_layout: wgpu::BindGroupLayout,
+    pub renderer: Renderer,
     pub video_status: VideoStatus,
+    audio: AudioPlayer,
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
 }
 
 impl State {
-    pub async fn new(window: &Window, videos: Vec<String>) -> Self {
+    pub async fn new(
+        window: &Window,
+        videos: Vec<String>,
+        reference: usize,
+        audio: Option<usize>,
+        input_options: InputOptions,
+    ) -> Self {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::Backends::all());
@@ -146,22 +43,7 @@ impl State {
             })
             .await
             .expect("created an adapter");
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    },
-                    label: None,
-                },
-                None,
-            )
-            .await
-            .expect("created device and queue");
+        let (device, queue) = renderer::request_device(&adapter).await;
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -172,180 +54,35 @@ impl State {
         };
         surface.configure(&device, &config);
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    // This should match the filterable field of the
-                    // corresponding Texture entry above.
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(64),
-                    },
-                    count: None,
-                },
-            ],
-            label: Some("texture_bind_group_layout"),
-        });
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render pipeline layout"),
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
-            });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("render pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc(), InstanceRaw::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
-
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-        let num_indices = INDICES.len() as u32;
-
-        let instances = videos
-            .iter()
-            .enumerate()
-            .map(|(x, video)| {
-                let aspect_ratio = config.height as f32 / config.width as f32;
-                let position = glam::Mat4::from_translation(glam::Vec3 {
-                    x: 1.0 * aspect_ratio,
-                    y: 0.0,
-                    z: 0.0,
-                }) * glam::Mat4::from_translation(glam::Vec3 {
-                    x: (2.0 * (x as f32 / (videos.len() - 1) as f32)),
-                    y: 0.0,
-                    z: 0.0,
-                }) * glam::Mat4::from_translation(glam::Vec3 {
-                    x: -1.0 * aspect_ratio,
-                    y: 0.0,
-                    z: 0.0,
-                });
-                let scale = glam::Mat4::from_scale(glam::Vec3 {
-                    x: 1.0 / videos.len() as f32,
-                    y: 1.0 / videos.len() as f32,
-                    z: 1.0,
-                });
-
-                let texture =
-                    crate::texture::VideoTexture::new(video, &device, &queue, Some(video));
-                let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    layout: &bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&texture.texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&texture.texture.sampler),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 2,
-                            resource: device
-                                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                                    label: Some("Uniform Buffer"),
-                                    contents: bytemuck::cast_slice(&[[0.0; 4]; 4]),
-                                    usage: wgpu::BufferUsages::UNIFORM
-                                        | wgpu::BufferUsages::COPY_DST,
-                                })
-                                .as_entire_binding(),
-                        },
-                    ],
-                    label: Some(&format!("texture bind group {}", x)),
-                });
-
-                Instance {
-                    transform: position * scale * generate_matrix(config.width, config.height),
-                    texture,
-                    texture_bind_group,
-                }
-            })
-            .collect::<Vec<_>>();
-
-        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
-        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Instance Buffer"),
-            contents: bytemuck::cast_slice(&instance_data),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        Self {
-            surface,
+        let renderer = Renderer::new(
             device,
             queue,
+            config.format,
+            (size.width, size.height),
+            videos
+                .into_iter()
+                .map(|video| {
+                    let source = source::open(&video, &input_options);
+                    (video, source)
+                })
+                .collect(),
+            reference,
+            input_options,
+        );
+
+        let mut state = Self {
+            surface,
             config,
             size,
-            render_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
-            instances,
-            instance_buffer,
-            bind_group_layout,
+            renderer,
             video_status: VideoStatus::Playing,
+            audio: AudioPlayer::new(audio::default_sink()),
+            cursor_position: winit::dpi::PhysicalPosition::new(0.0, 0.0),
+        };
+        if audio.is_some() {
+            state.select_audio(audio);
         }
+        state
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -353,126 +90,138 @@ impl State {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            self.surface.configure(self.renderer.device(), &self.config);
+            self.renderer.resize((new_size.width, new_size.height));
         }
     }
 
     pub fn toggle_video_status(&mut self, video_status: Option<VideoStatus>) {
-        self.video_status = video_status.unwrap_or_else(|| match self.video_status {
+        self.video_status = video_status.unwrap_or(match self.video_status {
             VideoStatus::Paused => VideoStatus::Playing,
             VideoStatus::Playing => VideoStatus::Paused,
         });
     }
 
-    pub fn update(&mut self) {
-        self.instances.iter_mut().for_each(|instance| {
-            let mx_total = generate_matrix(self.config.width, self.config.height);
-            let mx_ref: &[f32; 16] = mx_total.as_ref();
-            let transform_matrix =
-                self.device
-                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("Uniform Buffer"),
-                        contents: bytemuck::cast_slice(mx_ref),
-                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                    });
-            instance.texture_bind_group =
-                self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    layout: &self.bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(
-                                &instance.texture.texture.view,
-                            ),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(
-                                &instance.texture.texture.sampler,
-                            ),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 2,
-                            resource: transform_matrix.as_entire_binding(),
-                        },
-                    ],
-                    label: Some(&format!("texture bind group")),
-                });
-        })
+    pub fn cursor_moved(&mut self, position: winit::dpi::PhysicalPosition<f64>) {
+        self.cursor_position = position;
     }
 
-    pub fn get_next_frame(&mut self) {
-        self.instances.iter_mut().for_each(|instance| {
-            instance.texture.get_next_frame(&self.device, &self.queue);
-        });
+    /// Seek to the frame under the cursor when clicking in the metrics graph.
+    pub fn click(&mut self) {
+        let position = (self.cursor_position.x, self.cursor_position.y);
+        if let Some(index) = self
+            .renderer
+            .frame_at(position, (self.config.width, self.config.height))
+        {
+            self.renderer.seek(index);
+        }
     }
 
-    pub fn get_previous_frame(&mut self) {
-        self.instances.iter_mut().for_each(|instance| {
-            instance
-                .texture
-                .get_previous_frame(&self.device, &self.queue);
-        });
+    pub fn next_difference(&mut self) {
+        let jumped = self.renderer.next_difference();
+        self.pause_if(jumped);
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
+    pub fn previous_difference(&mut self) {
+        let jumped = self.renderer.previous_difference();
+        self.pause_if(jumped);
+    }
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+    pub fn next_worst_difference(&mut self) {
+        let jumped = self.renderer.next_worst_difference();
+        self.pause_if(jumped);
+    }
 
-            for (index, instance) in self.instances.iter().enumerate() {
-                let index = index as u32;
-                render_pass.set_bind_group(0, &instance.texture_bind_group, &[]);
-                render_pass.draw_indexed(0..self.num_indices, 0, index..(index + 1) as _);
+    /// Act on a control of a remote viewer like on the matching key.
+    pub fn control(&mut self, control: Control) {
+        match control {
+            Control::TogglePlayback => self.toggle_video_status(None),
+            Control::NextFrame => {
+                if self.video_status == VideoStatus::Paused {
+                    self.renderer.get_next_frame();
+                }
+            }
+            Control::PreviousFrame => {
+                if self.video_status == VideoStatus::Paused {
+                    self.renderer.get_previous_frame();
+                }
+            }
+            Control::NextReference => self.renderer.next_reference(),
+            Control::NextDifference => self.next_difference(),
+            Control::PreviousDifference => self.previous_difference(),
+            Control::Seek(index) => {
+                self.renderer.seek(index);
+                self.toggle_video_status(Some(VideoStatus::Paused));
             }
         }
+    }
 
-        // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+    /// Switch the video under the cursor, or the reference, to the next
+    /// video stream of its file.
+    pub fn next_stream(&mut self) {
+        let position = (self.cursor_position.x, self.cursor_position.y);
+        let index = self
+            .renderer
+            .video_at(position, (self.config.width, self.config.height))
+            .unwrap_or_else(|| self.renderer.reference());
+        let label = self.renderer.labels()[index].clone();
+        match self.renderer.next_stream(index) {
+            Some(stream) => println!("{}: showing stream {}", label, stream),
+            None => println!("{}: no other video stream to show", label),
+        }
+    }
 
-        Ok(())
+    /// Hear the next video, or none after the last one.
+    pub fn next_audio(&mut self) {
+        let next = match self.audio.selected() {
+            None => 0,
+            Some(index) => index + 1,
+        };
+        self.select_audio(Some(next).filter(|&next| next < self.renderer.labels().len()));
+    }
+
+    fn select_audio(&mut self, index: Option<usize>) {
+        let labels = self.renderer.labels();
+        let input = index
+            .filter(|&index| index < labels.len())
+            .map(|index| (index, labels[index].as_str()));
+        if !self.audio.select(input) {
+            println!("{} has no audio that can be played", input.unwrap().1);
+        }
+        match self.audio.selected() {
+            Some(index) => println!("hearing {}", labels[index]),
+            None => println!("audio off"),
+        }
+    }
+
+    /// Keep the audio heard in step with its video.
+    pub fn sync_audio(&mut self) {
+        if let Some((timestamp, frame_duration)) = self
+            .audio
+            .selected()
+            .and_then(|index| self.renderer.frame_time(index))
+        {
+            let playing = self.video_status == VideoStatus::Playing;
+            self.audio.sync(timestamp, frame_duration, playing);
+        }
+    }
+
+    /// Pause after jumping to a frame so it can be inspected.
+    fn pause_if(&mut self, jumped: bool) {
+        if jumped {
+            self.toggle_video_status(Some(VideoStatus::Paused));
+        }
     }
-}
 
-fn generate_matrix(width: u32, height: u32) -> glam::Mat4 {
-    let width = width as f32;
-    let height = height as f32;
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let output = self.surface.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.renderer
+            .draw(&view, (self.config.width, self.config.height));
+        output.present();
 
-    let aspect_ratio_scale = glam::Mat4::from_scale(glam::Vec3 {
-        x: 1.0,
-        y: width / height,
-        z: 1.0,
-    });
-    return aspect_ratio_scale;
+        Ok(())
+    }
 }
@@ -0,0 +1,483 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use ffmpeg_next::{format::context::Input, media::Type, subtitle::Rect, Rescale};
+
+use crate::font;
+use crate::overlay::{Color, Overlay};
+
+/// Extensions of subtitle files picked up next to an input, in order of
+/// preference.
+const SIDECARS: &[&str] = &["srt", "ass", "ssa", "vtt"];
+/// Largest scale of subtitle text, it shrinks to fit narrow videos.
+const TEXT_SCALE: f32 = 2.0;
+/// Distance of text from the edges of the video as a fraction of its size.
+const MARGIN: f32 = 0.05;
+/// Resolution ASS positions are given in when the script doesn't say.
+const DEFAULT_PLAY_RESOLUTION: (f32, f32) = (384.0, 288.0);
+const BACKGROUND: Color = [0.0, 0.0, 0.0, 0.6];
+const TEXT: Color = [1.0, 1.0, 1.0, 1.0];
+
+/// Lines of text and where they go, from the `\an` and `\pos` tags of ASS.
+struct Text {
+    lines: String,
+    /// Point of the text put in place like on a numeric keypad, 2 for the
+    /// middle of its bottom edge.
+    alignment: u8,
+    /// Where that point goes as a fraction of the size of the video, at the
+    /// margins for its alignment if `None`.
+    position: Option<(f32, f32)>,
+}
+
+enum Content {
+    Text(Text),
+    /// A picture such as a PGS subtitle, at `position` in the video.
+    Bitmap {
+        position: (u32, u32),
+        size: (u32, u32),
+        rgba: Vec<u8>,
+    },
+}
+
+/// Everything shown from `start` to `end`, in seconds on the clock of the
+/// video.
+struct Cue {
+    start: f64,
+    end: f64,
+    content: Vec<Content>,
+}
+
+/// The subtitles of an input, from a file next to it with the same name and
+/// one of the [`SIDECARS`] extensions or else from its best subtitle stream,
+/// decoded on a background thread. Text is drawn with the font of the
+/// overlay, which only has printable ASCII, other characters show as `?`.
+pub struct Subtitles {
+    cues: Arc<Mutex<Vec<Cue>>>,
+}
+
+impl Subtitles {
+    pub fn spawn(path: &str) -> Self {
+        let cues = Arc::new(Mutex::new(Vec::new()));
+        let thread_cues = cues.clone();
+        let path = crate::video::split_selection(path).0.to_string();
+        std::thread::spawn(move || {
+            // Inputs that can only be read once, like live streams and
+            // pipes, have none.
+            if !crate::source::can_reopen(&path) {
+                return;
+            }
+            if let Ok(video) = ffmpeg_next::format::input(&path) {
+                let start = video_start(&video);
+                let sidecar =
+                    sidecar(&path).and_then(|path| ffmpeg_next::format::input(&path).ok());
+                decode(sidecar.unwrap_or(video), start, &thread_cues);
+            }
+        });
+        Self { cues }
+    }
+
+    /// Add the subtitles shown at `time` to `overlay`, over a video of
+    /// `dimensions` drawn at `placement`, its left, top, width and height in
+    /// pixels.
+    pub fn draw(
+        &self,
+        overlay: &mut Overlay,
+        placement: (f32, f32, f32, f32),
+        dimensions: (u32, u32),
+        time: f64,
+    ) {
+        let cues = self.cues.lock().unwrap();
+        let (left, top, width, height) = placement;
+        // Text without a position stacks up from the bottom or down from the
+        // top.
+        let mut bottom = top + height * (1.0 - MARGIN);
+        let mut below = top + height * MARGIN;
+        for cue in cues
+            .iter()
+            .filter(|cue| cue.start <= time && time < cue.end)
+        {
+            for content in &cue.content {
+                match content {
+                    Content::Text(text) => {
+                        let at = match text.position {
+                            Some((x, y)) => (left + x * width, top + y * height),
+                            None => {
+                                let x = match text.alignment % 3 {
+                                    1 => left + width * MARGIN,
+                                    0 => left + width * (1.0 - MARGIN),
+                                    _ => left + width / 2.0,
+                                };
+                                match text.alignment {
+                                    7..=9 => (x, below),
+                                    4..=6 => (x, top + height / 2.0),
+                                    _ => (x, bottom),
+                                }
+                            }
+                        };
+                        let (text_top, text_bottom) = draw_text(overlay, text, width, at);
+                        if text.position.is_none() {
+                            match text.alignment {
+                                7..=9 => below = text_bottom,
+                                4..=6 => {}
+                                _ => bottom = text_top,
+                            }
+                        }
+                    }
+                    Content::Bitmap {
+                        position,
+                        size,
+                        rgba,
+                    } => {
+                        let scale = (
+                            width / dimensions.0.max(1) as f32,
+                            height / dimensions.1.max(1) as f32,
+                        );
+                        let at = (
+                            left + position.0 as f32 * scale.0,
+                            top + position.1 as f32 * scale.1,
+                        );
+                        draw_bitmap(overlay, at, scale, *size, rgba);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The first sidecar subtitle file of the video at `path`.
+fn sidecar(path: &str) -> Option<String> {
+    SIDECARS
+        .iter()
+        .map(|extension| Path::new(path).with_extension(extension))
+        .find(|sidecar| sidecar.is_file())
+        .map(|sidecar| sidecar.to_string_lossy().into_owned())
+}
+
+/// Time in seconds of the start of the best video stream, which frames are
+/// timed from.
+fn video_start(ictx: &Input) -> f64 {
+    ictx.streams()
+        .best(Type::Video)
+        .map(|stream| {
+            // AV_NOPTS_VALUE when the container doesn't know
+            let start_time = Some(stream.start_time())
+                .filter(|&start_time| start_time != i64::MIN)
+                .unwrap_or(0);
+            start_time as f64 * f64::from(stream.time_base())
+        })
+        .unwrap_or(0.0)
+}
+
+/// Decode the best subtitle stream of `ictx` into `cues`, timed from
+/// `video_start`.
+fn decode(mut ictx: Input, video_start: f64, cues: &Mutex<Vec<Cue>>) {
+    let (stream_index, time_base, mut decoder) = match ictx.streams().best(Type::Subtitle) {
+        Some(stream) => {
+            let decoder =
+                ffmpeg_next::codec::context::Context::from_parameters(stream.parameters())
+                    .and_then(|context| context.decoder().subtitle());
+            match decoder {
+                Ok(decoder) => (stream.index(), stream.time_base(), decoder),
+                Err(_) => return,
+            }
+        }
+        None => return,
+    };
+    // The script info of ASS, which text subtitles are converted to.
+    let header = unsafe {
+        let context = &*decoder.as_ptr();
+        if context.subtitle_header.is_null() {
+            String::new()
+        } else {
+            let header = std::slice::from_raw_parts(
+                context.subtitle_header,
+                context.subtitle_header_size as usize,
+            );
+            String::from_utf8_lossy(header).into_owned()
+        }
+    };
+    let resolution = play_resolution(&header);
+
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        let mut subtitle = ffmpeg_next::Subtitle::new();
+        // Broken packets are skipped.
+        if !matches!(decoder.decode(&packet, &mut subtitle), Ok(true)) {
+            continue;
+        }
+        let pts = match subtitle.pts().or_else(|| {
+            packet
+                .pts()
+                .map(|pts| pts.rescale(time_base, ffmpeg_next::rescale::TIME_BASE))
+        }) {
+            Some(pts) => pts as f64 / 1_000_000.0 - video_start,
+            None => continue,
+        };
+        let start = pts + subtitle.start() as f64 / 1000.0;
+        let end = if subtitle.end() > subtitle.start() && subtitle.end() != u32::MAX {
+            pts + subtitle.end() as f64 / 1000.0
+        } else if packet.duration() > 0 {
+            start + packet.duration() as f64 * f64::from(time_base)
+        } else {
+            // Until the next one, as with PGS.
+            f64::INFINITY
+        };
+        let content = subtitle
+            .rects()
+            .filter_map(|rect| content(rect, resolution))
+            .collect::<Vec<_>>();
+
+        let mut cues = cues.lock().unwrap();
+        if let Some(last) = cues.last_mut() {
+            if last.end.is_infinite() {
+                last.end = start.max(last.start);
+            }
+        }
+        // An empty subtitle only clears the one before.
+        if !content.is_empty() {
+            cues.push(Cue {
+                start,
+                end,
+                content,
+            });
+        }
+    }
+}
+
+/// What `rect` shows, with ASS positions in `resolution`.
+fn content(rect: Rect, resolution: (f32, f32)) -> Option<Content> {
+    match rect {
+        Rect::Text(text) => Some(Content::Text(Text {
+            lines: text.get().to_string(),
+            alignment: 2,
+            position: None,
+        })),
+        Rect::Ass(ass) => Some(Content::Text(ass_text(ass.get(), resolution))),
+        Rect::Bitmap(bitmap) => {
+            let size = (bitmap.width(), bitmap.height());
+            let mut rgba = Vec::with_capacity((size.0 * size.1 * 4) as usize);
+            // ffmpeg-next doesn't expose the picture of a bitmap: data[0] has
+            // a palette index for every pixel and data[1] the palette as
+            // native endian ARGB.
+            unsafe {
+                let rect = &*bitmap.as_ptr();
+                if rect.data[0].is_null() || rect.data[1].is_null() {
+                    return None;
+                }
+                let palette = rect.data[1] as *const u32;
+                for y in 0..size.1 as isize {
+                    let row = rect.data[0].offset(y * rect.linesize[0] as isize);
+                    for x in 0..size.0 as isize {
+                        let index = (*row.offset(x) as usize).min(bitmap.colors().max(1) - 1);
+                        let argb = *palette.add(index);
+                        rgba.extend([
+                            (argb >> 16) as u8,
+                            (argb >> 8) as u8,
+                            argb as u8,
+                            (argb >> 24) as u8,
+                        ]);
+                    }
+                }
+            }
+            Some(Content::Bitmap {
+                position: (bitmap.x() as u32, bitmap.y() as u32),
+                size,
+                rgba,
+            })
+        }
+        Rect::None(_) => None,
+    }
+}
+
+/// Resolution of the ASS script info in `header`, which positions are given
+/// in.
+fn play_resolution(header: &str) -> (f32, f32) {
+    let value = |key: &str| {
+        header
+            .lines()
+            .find_map(|line| line.strip_prefix(key))
+            .and_then(|value| value.trim().parse::<f32>().ok())
+            .filter(|&value| value > 0.0)
+    };
+    (
+        value("PlayResX:").unwrap_or(DEFAULT_PLAY_RESOLUTION.0),
+        value("PlayResY:").unwrap_or(DEFAULT_PLAY_RESOLUTION.1),
+    )
+}
+
+/// The text of an ASS event without its fields and override tags, as ffmpeg
+/// gives every text subtitle, placed by its `\an` and `\pos` tags with
+/// positions in `resolution`.
+fn ass_text(event: &str, resolution: (f32, f32)) -> Text {
+    // ReadOrder, Layer, Style, Name, MarginL, MarginR, MarginV, Effect, Text,
+    // with a Dialogue line of older versions also having Start and End.
+    let fields = if event.starts_with("Dialogue:") {
+        10
+    } else {
+        9
+    };
+    let text = event.splitn(fields, ',').last().unwrap_or(event);
+
+    let mut plain = String::new();
+    let mut tags = String::new();
+    let mut in_tag = false;
+    for character in text.chars() {
+        match character {
+            '{' => in_tag = true,
+            '}' => in_tag = false,
+            _ if in_tag => tags.push(character),
+            _ => plain.push(character),
+        }
+    }
+
+    let mut alignment = 2;
+    let mut position = None;
+    for tag in tags.split('\\') {
+        if let Some(value) = tag.strip_prefix("an") {
+            if let Ok(value @ 1..=9) = value.trim().parse::<u8>() {
+                alignment = value;
+            }
+        } else if let Some(arguments) = tag.strip_prefix("pos(") {
+            let arguments = arguments.trim_end().trim_end_matches(')');
+            if let Some((x, y)) = arguments.split_once(',') {
+                if let (Ok(x), Ok(y)) = (x.trim().parse::<f32>(), y.trim().parse::<f32>()) {
+                    position = Some((x / resolution.0, y / resolution.1));
+                }
+            }
+        }
+    }
+
+    Text {
+        lines: plain
+            .replace("\\N", "\n")
+            .replace("\\n", "\n")
+            .replace("\\h", " "),
+        alignment,
+        position,
+    }
+}
+
+/// Draw the lines of `text` with the point given by its alignment `at`,
+/// shrinking them to fit a video of `width`. Returns the top and bottom of
+/// the text for what goes next to it.
+fn draw_text(overlay: &mut Overlay, text: &Text, width: f32, at: (f32, f32)) -> (f32, f32) {
+    let lines = text.lines.lines().collect::<Vec<_>>();
+    let widest = lines
+        .iter()
+        .map(|line| Overlay::text_size(line, 1.0).0)
+        .fold(0.0, f32::max);
+    let scale = (width * 0.9 / widest.max(1.0)).clamp(1.0, TEXT_SCALE);
+    let line_height = (font::GLYPH_SIZE.1 + 4) as f32 * scale;
+    let height = lines.len() as f32 * line_height;
+
+    let top = match text.alignment {
+        7..=9 => at.1,
+        4..=6 => at.1 - height / 2.0,
+        _ => at.1 - height,
+    };
+    let mut y = top;
+    for line in lines {
+        let (line_width, text_height) = Overlay::text_size(line, scale);
+        let x = match text.alignment % 3 {
+            1 => at.0,
+            0 => at.0 - line_width,
+            _ => at.0 - line_width / 2.0,
+        };
+        overlay.rect(
+            x - scale * 2.0,
+            y - scale * 2.0,
+            line_width + scale * 4.0,
+            text_height + scale * 4.0,
+            BACKGROUND,
+        );
+        overlay.text(x, y, scale, line, TEXT);
+        y += line_height;
+    }
+    (top, top + height)
+}
+
+/// Draw a bitmap of `size` with its top left corner `at`, scaled by `scale`,
+/// as runs of pixels of the same colour.
+fn draw_bitmap(
+    overlay: &mut Overlay,
+    at: (f32, f32),
+    scale: (f32, f32),
+    size: (u32, u32),
+    rgba: &[u8],
+) {
+    let rows = (size.1 as f32 * scale.1).ceil() as u32;
+    let columns = (size.0 as f32 * scale.0).ceil() as u32;
+    if size.0 == 0 || size.1 == 0 {
+        return;
+    }
+    let pixel = |column: u32, row: u32| {
+        let x = ((column as f32 / scale.0) as u32).min(size.0 - 1);
+        let y = ((row as f32 / scale.1) as u32).min(size.1 - 1);
+        let offset = ((y * size.0 + x) * 4) as usize;
+        [
+            rgba[offset],
+            rgba[offset + 1],
+            rgba[offset + 2],
+            rgba[offset + 3],
+        ]
+    };
+
+    for row in 0..rows {
+        let mut column = 0;
+        while column < columns {
+            let color = pixel(column, row);
+            let start = column;
+            while column < columns && pixel(column, row) == color {
+                column += 1;
+            }
+            if color[3] > 0 {
+                overlay.rect(
+                    at.0 + start as f32,
+                    at.1 + row as f32,
+                    (column - start) as f32,
+                    1.0,
+                    color.map(|channel| channel as f32 / 255.0),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ass_text_without_tags() {
+        let text = ass_text("0,0,Default,,0,0,0,,Hello\\Nworld", DEFAULT_PLAY_RESOLUTION);
+        assert_eq!(text.lines, "Hello\nworld");
+        assert_eq!(text.alignment, 2);
+        assert_eq!(text.position, None);
+    }
+
+    #[test]
+    fn ass_text_alignment_and_position() {
+        let text = ass_text(
+            "0,0,Default,,0,0,0,,{\\an8\\i1}Top{\\i0}",
+            DEFAULT_PLAY_RESOLUTION,
+        );
+        assert_eq!(text.lines, "Top");
+        assert_eq!(text.alignment, 8);
+
+        let text = ass_text(
+            "0,0,Default,,0,0,0,,{\\pos(960,540)\\an5}Middle",
+            (1920.0, 1080.0),
+        );
+        assert_eq!(text.alignment, 5);
+        assert_eq!(text.position, Some((0.5, 0.5)));
+    }
+
+    #[test]
+    fn play_resolution_of_header() {
+        let header = "[Script Info]\nScriptType: v4.00+\nPlayResX: 1280\nPlayResY: 720\n";
+        assert_eq!(play_resolution(header), (1280.0, 720.0));
+        assert_eq!(play_resolution(""), DEFAULT_PLAY_RESOLUTION);
+    }
+}
@@ -1,8 +1,12 @@
+use ffmpeg_next::format::Pixel;
+
+use crate::source::{Converter, FrameSource};
+
 pub struct Texture {
-    pub texture: wgpu::Texture,
+    /// Owns what `view` shows.
+    _texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
-    pub dimensions: (u32, u32),
 }
 
 impl Texture {
@@ -35,7 +39,7 @@ impl Texture {
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            &bytes,
+            bytes,
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
@@ -56,73 +60,107 @@ impl Texture {
         });
 
         Self {
-            texture,
+            _texture: texture,
             view,
             sampler,
-            dimensions,
         }
     }
 }
 
 pub struct VideoTexture {
-    pub stream: crate::VideoStream,
+    pub source: Box<dyn FrameSource>,
     pub texture: Texture,
+    /// The displayed frame as it came out of the source.
+    pub frame: ffmpeg_next::util::frame::Video,
+    rgba: Converter,
     last_update: std::time::Instant,
 }
 
 impl VideoTexture {
-    pub fn new(
-        path: &str,
+    pub fn from_source(
+        mut source: Box<dyn FrameSource>,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         label: Option<&str>,
     ) -> Self {
-        let mut stream = crate::VideoStream::new(path);
-        let frame = stream.get_next_frame().unwrap();
+        let frame = source.next_frame().expect("a first frame");
+        let mut rgba = Converter::new(Pixel::RGBA, None);
+        let converted = rgba.convert(&frame);
         let texture = Texture::from_frame(
             device,
             queue,
-            frame.data(0),
-            (frame.width(), frame.height()),
+            converted.data(0),
+            (converted.width(), converted.height()),
             label,
         );
 
         Self {
-            stream,
+            source,
             texture,
+            frame,
+            rgba,
             last_update: std::time::Instant::now(),
         }
     }
 
     pub fn get_next_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
-        if self.last_update.elapsed().as_millis() > self.stream.frame_time() {
-            let frame = self.stream.get_next_frame().unwrap();
-            self.texture = Texture::from_frame(
-                device,
-                queue,
-                frame.data(0),
-                (frame.width(), frame.height()),
-                None,
-            );
-            self.last_update = std::time::Instant::now();
-            return true;
+        if self.last_update.elapsed().as_secs_f64() * f64::from(self.source.frame_rate()) >= 1.0 {
+            return self.next_frame(device, queue);
         }
         false
     }
 
+    /// Show the next frame regardless of playback timing, false at the end of
+    /// the video.
+    pub fn next_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let frame = self.source.next_frame();
+        self.show(device, queue, frame)
+    }
+
+    pub fn seek(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, index: usize) -> bool {
+        let frame = self.source.seek(index);
+        self.show(device, queue, frame)
+    }
+
     pub fn get_previous_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
-        if self.last_update.elapsed().as_millis() > self.stream.frame_time() {
-            let frame = self.stream.get_previous_frame().unwrap();
-            self.texture = Texture::from_frame(
-                device,
-                queue,
-                frame.data(0),
-                (frame.width(), frame.height()),
-                None,
-            );
-            self.last_update = std::time::Instant::now();
-            return true;
+        match self.source.frame_index().checked_sub(1) {
+            Some(index) => self.seek(device, queue, index),
+            None => false,
+        }
+    }
+
+    /// Show video stream `index` of the input instead, false if it can't.
+    pub fn select_stream(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+    ) -> bool {
+        let frame = self.source.select_stream(index);
+        self.show(device, queue, frame)
+    }
+
+    fn show(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: Option<ffmpeg_next::util::frame::Video>,
+    ) -> bool {
+        match frame {
+            Some(frame) => {
+                let converted = self.rgba.convert(&frame);
+                self.texture = Texture::from_frame(
+                    device,
+                    queue,
+                    converted.data(0),
+                    (converted.width(), converted.height()),
+                    None,
+                );
+                self.frame = frame;
+                self.last_update = std::time::Instant::now();
+                true
+            }
+            None => false,
         }
-        false
     }
 }
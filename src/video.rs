@@ -1,70 +1,636 @@
-use ffmpeg_next::{decoder::Video, format::context::Input, software::scaling::Context};
+use ffmpeg_next::{
+    codec::discard::Discard, decoder::Video, format::context::Input, format::Pixel, picture,
+    software::scaling::Context, Rational, Rescale,
+};
 
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+
+use crate::info::{MediaInfo, StreamInfo};
+use crate::live::{self, Live, LiveStatus};
+use crate::source::FrameSource;
+
+/// Which video stream of a file to decode, given after a `#` at the end of
+/// its path: `v:N` for the Nth video stream counting from 0, `v:LANG` for
+/// the first one in a language such as `eng`, or `N` for stream N of the
+/// file, e.g. `file.mkv#v:1`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamSelection {
+    Best,
+    Video(usize),
+    Language(String),
+    Index(usize),
+}
+
+impl StreamSelection {
+    fn parse(selector: &str) -> Option<Self> {
+        match selector.strip_prefix("v:") {
+            Some(video) => match video.parse() {
+                Ok(number) => Some(StreamSelection::Video(number)),
+                Err(_) if !video.is_empty() && video.chars().all(|c| c.is_ascii_alphabetic()) => {
+                    Some(StreamSelection::Language(video.to_string()))
+                }
+                Err(_) => None,
+            },
+            None => selector.parse().ok().map(StreamSelection::Index),
+        }
+    }
+
+    /// Index of the selected stream in `ictx`.
+    pub(crate) fn find(&self, ictx: &Input) -> Option<usize> {
+        let mut videos = ictx
+            .streams()
+            .filter(|stream| stream.parameters().medium() == ffmpeg_next::media::Type::Video);
+        match self {
+            StreamSelection::Best => ictx
+                .streams()
+                .best(ffmpeg_next::media::Type::Video)
+                .map(|stream| stream.index()),
+            StreamSelection::Video(number) => videos.nth(*number).map(|stream| stream.index()),
+            StreamSelection::Language(language) => videos
+                .find(|stream| stream.metadata().get("language") == Some(language.as_str()))
+                .map(|stream| stream.index()),
+            StreamSelection::Index(index) => videos
+                .find(|stream| stream.index() == *index)
+                .map(|stream| stream.index()),
+        }
+    }
+}
+
+impl fmt::Display for StreamSelection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamSelection::Best => write!(f, "best"),
+            StreamSelection::Video(number) => write!(f, "v:{}", number),
+            StreamSelection::Language(language) => write!(f, "v:{}", language),
+            StreamSelection::Index(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+/// Split the stream selection off the end of `path`, unless `path` is an
+/// existing file with a `#` in its name.
+pub fn split_selection(path: &str) -> (&str, StreamSelection) {
+    if !std::path::Path::new(path).exists() {
+        if let Some((file, selector)) = path.rsplit_once('#') {
+            if let Some(selection) = StreamSelection::parse(selector) {
+                return (file, selection);
+            }
+        }
+    }
+    (path, StreamSelection::Best)
+}
+
+/// A frame as it is stored in the stream, see [`VideoStream::coded_frames`].
+#[derive(Clone, Copy, Debug)]
+pub struct CodedFrame {
+    pub index: usize,
+    /// Size of its packet in bytes.
+    pub size: usize,
+    pub kind: picture::Type,
+}
+
+/// Where the packets of a [`VideoStream`] come from.
+enum Demuxer {
+    File(Input),
+    /// Packets of a live stream read on another thread, see [`read_live`].
+    Live(Receiver<Demuxed>),
+}
+
+/// What the thread reading a live stream passes on.
+enum Demuxed {
+    Packet(ffmpeg_next::Packet),
+    /// Packets were skipped up to a keyframe to catch up with the stream.
+    Dropped(usize),
+    /// The stream stopped and is being reopened.
+    Reconnecting,
+    /// The stream was reopened, timestamps start over from `start_time` in
+    /// `time_base`.
+    Reopened {
+        time_base: Rational,
+        start_time: i64,
+    },
+}
+
+/// Decodes a video stream of a file with ffmpeg, frame by frame, the best
+/// one unless selected otherwise.
 pub struct VideoStream {
+    url: String,
+    /// Format and size frames are converted to, the native size if `None`.
+    /// Streams without one only decode.
+    output: Option<(Pixel, Option<(u32, u32)>)>,
+    streams: Vec<StreamInfo>,
+    media_info: MediaInfo,
     stream_index: usize,
-    scaler: Context,
+    scaler: Option<Context>,
     decoder: Video,
-    ictx: Input,
+    demuxer: Demuxer,
+    dimensions: (u32, u32),
+    time_base: Rational,
+    start_time: i64,
+    frame_rate: Rational,
+    frame_index: Option<usize>,
+    /// Whether the end of the input was sent to the decoder, which then only
+    /// gives back the frames it still holds, as with B-frames.
+    eof: bool,
+    live: Option<Live>,
 }
 
 impl VideoStream {
+    /// Open `path`, or stdin for `-`, converting frames to RGBA at the native
+    /// size.
     pub fn new(path: &str) -> Self {
-        let ictx = ffmpeg_next::format::input(&path.to_string()).expect("to read the input");
+        Self::with_output(path, Pixel::RGBA, None)
+    }
+
+    /// Open `path` and convert every decoded frame to `format`, scaled to
+    /// `dimensions` if given and to the native size of the video otherwise.
+    pub fn with_output(path: &str, format: Pixel, dimensions: Option<(u32, u32)>) -> Self {
+        Self::with_selection(path, &StreamSelection::Best, format, dimensions)
+    }
 
-        let input = ictx
+    /// Open the video stream of `path` chosen by `selection`, see
+    /// [`VideoStream::with_output`].
+    pub fn with_selection(
+        path: &str,
+        selection: &StreamSelection,
+        format: Pixel,
+        dimensions: Option<(u32, u32)>,
+    ) -> Self {
+        Self::open(path, selection, Some((format, dimensions)))
+    }
+
+    /// Open the video stream of `path` chosen by `selection` without
+    /// converting frames, to read them as they come out of the decoder with
+    /// [`VideoStream::get_next_decoded_frame`] or as a [`FrameSource`].
+    pub fn decoded(path: &str, selection: &StreamSelection) -> Self {
+        Self::open(path, selection, None)
+    }
+
+    fn open(
+        path: &str,
+        selection: &StreamSelection,
+        output: Option<(Pixel, Option<(u32, u32)>)>,
+    ) -> Self {
+        let url = if path == "-" { "pipe:0" } else { path };
+        let ictx = ffmpeg_next::format::input(&url.to_string()).expect("to read the input");
+        let index = selection
+            .find(&ictx)
+            .unwrap_or_else(|| panic!("no video stream {} in {}", selection, path));
+        Self::from_input(url, ictx, index, output, false)
+    }
+
+    /// Open the live stream at `url`, see [`live::options`]. Packets lost on
+    /// the way are skipped and the stream is reopened in the background when
+    /// it stops.
+    pub fn live(url: &str) -> Self {
+        ffmpeg_next::format::network::init();
+        let ictx = ffmpeg_next::format::input_with_dictionary(&url.to_string(), live::options(url))
+            .expect("to connect to the stream");
+        let index = StreamSelection::Best.find(&ictx).expect("video stream");
+        Self::from_input(url, ictx, index, None, true)
+    }
+
+    /// Decode stream `stream_index` of `ictx`, opened from `url`, converting
+    /// frames to `output` if given. Live streams are read on another thread.
+    fn from_input(
+        url: &str,
+        ictx: Input,
+        stream_index: usize,
+        output: Option<(Pixel, Option<(u32, u32)>)>,
+        live: bool,
+    ) -> Self {
+        let streams = ictx
             .streams()
-            .best(ffmpeg_next::media::Type::Video)
-            .expect("video stream");
-        let stream_index = input.index();
+            .map(|stream| StreamInfo::new(&stream, stream.index() == stream_index))
+            .collect();
+        let input = ictx.stream(stream_index).expect("video stream");
+        let time_base = input.time_base();
+        let start_time = start_time(&input);
+        let frame_rate = Some(input.avg_frame_rate())
+            .filter(|frame_rate| frame_rate.numerator() > 0)
+            .unwrap_or_else(|| input.rate());
 
         let context_decoder =
             ffmpeg_next::codec::context::Context::from_parameters(input.parameters())
                 .expect("context decoder");
-        let decoder = context_decoder.decoder().video().expect("video decoder");
-
-        let scaler = Context::get(
-            decoder.format(),
-            decoder.width(),
-            decoder.height(),
-            ffmpeg_next::format::Pixel::RGBA,
-            decoder.width(),
-            decoder.height(),
-            ffmpeg_next::software::scaling::flag::Flags::BILINEAR,
-        )
-        .expect("scaler");
+        let codec = ffmpeg_next::decoder::find(context_decoder.id()).expect("video decoder");
+        let mut options = ffmpeg_next::Dictionary::new();
+        // The QPs of every frame for the info panel, from the decoders that
+        // have them.
+        options.set("export_side_data", "venc_params");
+        let decoder = context_decoder
+            .decoder()
+            .open_as_with(codec, options)
+            .and_then(|opened| opened.video())
+            .expect("video decoder");
+        let media_info = MediaInfo::new(&ictx, &input, &decoder, frame_rate);
+
+        let native = (decoder.width(), decoder.height());
+        let dimensions = output
+            .and_then(|(_, dimensions)| dimensions)
+            .unwrap_or(native);
+        let scaler = output.map(|(format, _)| {
+            Context::get(
+                decoder.format(),
+                native.0,
+                native.1,
+                format,
+                dimensions.0,
+                dimensions.1,
+                ffmpeg_next::software::scaling::flag::Flags::BILINEAR,
+            )
+            .expect("scaler")
+        });
+
+        let demuxer = if live {
+            let (sender, packets) = mpsc::sync_channel(live::QUEUED_PACKETS);
+            let url = url.to_string();
+            std::thread::spawn(move || read_live(&url, ictx, stream_index, &sender));
+            Demuxer::Live(packets)
+        } else {
+            Demuxer::File(ictx)
+        };
 
         Self {
+            url: url.to_string(),
+            output,
+            streams,
+            media_info,
             stream_index,
             scaler,
             decoder,
-            ictx,
+            demuxer,
+            dimensions,
+            time_base,
+            start_time,
+            frame_rate,
+            frame_index: None,
+            eof: false,
+            live: live.then(Live::new),
         }
     }
 
     pub fn get_next_frame(&mut self) -> Option<ffmpeg_next::util::frame::Video> {
-        for (stream, packet) in self.ictx.packets() {
-            if stream.index() == self.stream_index {
-                self.decoder.send_packet(&packet).expect("send packet");
-                let mut decoded = ffmpeg_next::util::frame::Video::empty();
-
-                if self.decoder.receive_frame(&mut decoded).is_ok() {
-                    let mut rgb_frame = ffmpeg_next::util::frame::Video::empty();
-                    self.scaler
-                        .run(&decoded, &mut rgb_frame)
-                        .expect("scale frame");
-                    return Some(rgb_frame);
-                }
+        let decoded = self.get_next_decoded_frame()?;
+        Some(self.scale(&decoded))
+    }
+
+    /// Next frame as it comes out of the decoder, in its native format and size.
+    pub fn get_next_decoded_frame(&mut self) -> Option<ffmpeg_next::util::frame::Video> {
+        let decoded = self.receive_frame()?;
+        let timestamp = decoded.timestamp();
+        if let (Some(live), Some(timestamp)) = (&mut self.live, timestamp) {
+            live.frame((timestamp - self.start_time) as f64 * f64::from(self.time_base));
+        }
+        // Timestamps of live streams start over when reconnecting, so their
+        // frames are simply counted.
+        self.frame_index = Some(match timestamp {
+            Some(timestamp) if self.live.is_none() => self.timestamp_to_index(timestamp),
+            _ => self.frame_index.map_or(0, |index| index + 1),
+        });
+        Some(decoded)
+    }
+
+    /// Convert a decoded frame to the output format and dimensions of this
+    /// stream, which it must have been opened with.
+    pub fn scale(
+        &mut self,
+        decoded: &ffmpeg_next::util::frame::Video,
+    ) -> ffmpeg_next::util::frame::Video {
+        let mut scaled = ffmpeg_next::util::frame::Video::empty();
+        self.scaler
+            .as_mut()
+            .expect("a stream opened with an output format")
+            .run(decoded, &mut scaled)
+            .expect("scale frame");
+        scaled
+    }
+
+    /// Seek to `index` and return the decoded frame there. Seeking lands on the
+    /// closest keyframe before it, from where we decode forward.
+    pub fn seek(&mut self, index: usize) -> Option<ffmpeg_next::util::frame::Video> {
+        let timestamp = self
+            .index_to_timestamp(index)
+            .rescale(self.time_base, ffmpeg_next::rescale::TIME_BASE);
+        match &mut self.demuxer {
+            Demuxer::File(ictx) => ictx.seek(timestamp, ..timestamp).ok()?,
+            Demuxer::Live(_) => return None,
+        }
+        self.decoder.flush();
+        self.eof = false;
+        self.frame_index = None;
+
+        while let Some(decoded) = self.get_next_decoded_frame() {
+            if self.frame_index() >= index {
+                return Some(decoded);
             }
         }
         None
     }
 
+    /// Switch to video stream `index` of the file, at the time of the frame
+    /// shown, and return the frame there. Live streams and stdin can't
+    /// switch.
+    pub fn select_stream(&mut self, index: usize) -> Option<ffmpeg_next::util::frame::Video> {
+        let is_video = self.streams.iter().any(|stream| {
+            stream.index == index && stream.medium == ffmpeg_next::media::Type::Video
+        });
+        if self.live.is_some() || self.url == "pipe:0" || !is_video {
+            return None;
+        }
+        let seconds = self.frame_index() as f64 / f64::from(self.frame_rate);
+        let ictx = ffmpeg_next::format::input(&self.url).ok()?;
+        *self = Self::from_input(&self.url.clone(), ictx, index, self.output, false);
+        let frame_index = (seconds * f64::from(self.frame_rate)).round() as usize;
+        self.seek(frame_index)
+    }
+
+    /// Size and picture type of every frame from the current one on. Without
+    /// B-frames, which the decoder tells from the headers, frames are read
+    /// from the packets, keyframes being I-frames and the others P-frames.
+    /// Otherwise only as much is decoded as needed to tell the picture type,
+    /// which is still a lot faster than decoding the pictures.
+    pub fn coded_frames(mut self) -> Box<dyn Iterator<Item = CodedFrame>> {
+        if !self.decoder.has_b_frames() {
+            return Box::new(std::iter::from_fn(move || {
+                let packet = self.next_packet()?;
+                // Packets are in presentation order without B-frames.
+                let index = match packet.pts().or_else(|| packet.dts()) {
+                    Some(timestamp) => self.timestamp_to_index(timestamp),
+                    None => self.frame_index.map_or(0, |index| index + 1),
+                };
+                self.frame_index = Some(index);
+                let kind = if packet.is_key() {
+                    picture::Type::I
+                } else {
+                    picture::Type::P
+                };
+                Some(CodedFrame {
+                    index,
+                    size: packet.size(),
+                    kind,
+                })
+            }));
+        }
+
+        self.decoder.skip_idct(Discard::All);
+        self.decoder.skip_loop_filter(Discard::All);
+        Box::new(std::iter::from_fn(move || {
+            let decoded = self.get_next_decoded_frame()?;
+            Some(CodedFrame {
+                index: self.frame_index(),
+                size: decoded.packet().size,
+                kind: decoded.kind(),
+            })
+        }))
+    }
+
+    /// Index of the frame most recently returned by this stream.
+    pub fn frame_index(&self) -> usize {
+        self.frame_index.unwrap_or(0)
+    }
+
+    fn receive_frame(&mut self) -> Option<ffmpeg_next::util::frame::Video> {
+        let mut decoded = ffmpeg_next::util::frame::Video::empty();
+        loop {
+            // Frames the decoder holds come first, it can give several for
+            // the packets of reordered frames.
+            if self.decoder.receive_frame(&mut decoded).is_ok() {
+                return Some(decoded);
+            }
+            if self.eof {
+                return None;
+            }
+            let packet = self.next_packet();
+            match packet {
+                Some(packet) => {
+                    if self.decoder.send_packet(&packet).is_err() {
+                        // Lost or corrupt data, carry on with the next packet.
+                        if let Some(live) = &mut self.live {
+                            live.status.dropped += 1;
+                        }
+                    }
+                }
+                None => {
+                    // Drain the frames held back until the end.
+                    self.decoder.send_eof().ok()?;
+                    self.eof = true;
+                }
+            }
+        }
+    }
+
+    /// Next packet of the video stream, `None` at the end of a file or once
+    /// a live stream is given up.
+    fn next_packet(&mut self) -> Option<ffmpeg_next::Packet> {
+        let stream_index = self.stream_index;
+        let packets = match &mut self.demuxer {
+            Demuxer::File(ictx) => {
+                return ictx
+                    .packets()
+                    .find(|(stream, _)| stream.index() == stream_index)
+                    .map(|(_, packet)| packet)
+            }
+            Demuxer::Live(packets) => packets,
+        };
+        loop {
+            match packets.recv().ok()? {
+                Demuxed::Packet(packet) => return Some(packet),
+                Demuxed::Dropped(count) => {
+                    if let Some(live) = &mut self.live {
+                        live.status.dropped += count;
+                    }
+                }
+                Demuxed::Reconnecting => {
+                    if let Some(live) = &mut self.live {
+                        live.reconnect();
+                    }
+                }
+                Demuxed::Reopened {
+                    time_base,
+                    start_time,
+                } => {
+                    self.time_base = time_base;
+                    self.start_time = start_time;
+                    self.decoder.flush();
+                }
+            }
+        }
+    }
+
+    fn timestamp_to_index(&self, timestamp: i64) -> usize {
+        let seconds = (timestamp - self.start_time) as f64 * f64::from(self.time_base);
+        (seconds * f64::from(self.frame_rate)).round().max(0.0) as usize
+    }
+
+    fn index_to_timestamp(&self, index: usize) -> i64 {
+        let seconds = index as f64 / f64::from(self.frame_rate);
+        self.start_time + (seconds / f64::from(self.time_base)).round() as i64
+    }
+
+    /// Decoded frame before the current one.
     pub fn get_previous_frame(&mut self) -> Option<ffmpeg_next::util::frame::Video> {
-        todo!();
+        let index = self.frame_index().checked_sub(1)?;
+        self.seek(index)
+    }
+
+    /// Average number of frames per second.
+    pub fn frame_rate(&self) -> Rational {
+        self.frame_rate
+    }
+
+    /// Size of the frames returned by [`VideoStream::get_next_frame`], after
+    /// scaling. [`FrameSource::dimensions`] is the size as decoded.
+    pub fn output_dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+}
+
+impl FrameSource for VideoStream {
+    fn next_frame(&mut self) -> Option<ffmpeg_next::util::frame::Video> {
+        self.get_next_decoded_frame()
+    }
+
+    fn seek(&mut self, index: usize) -> Option<ffmpeg_next::util::frame::Video> {
+        VideoStream::seek(self, index)
+    }
+
+    fn frame_index(&self) -> usize {
+        VideoStream::frame_index(self)
+    }
+
+    fn frame_rate(&self) -> Rational {
+        VideoStream::frame_rate(self)
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.decoder.width(), self.decoder.height())
+    }
+
+    fn format(&self) -> Pixel {
+        self.decoder.format()
+    }
+
+    fn live_status(&self) -> Option<LiveStatus> {
+        self.live.as_ref().map(|live| live.status)
+    }
+
+    fn streams(&self) -> Vec<StreamInfo> {
+        self.streams.clone()
+    }
+
+    fn select_stream(&mut self, index: usize) -> Option<ffmpeg_next::util::frame::Video> {
+        VideoStream::select_stream(self, index)
+    }
+
+    fn media_info(&self) -> Option<MediaInfo> {
+        Some(self.media_info.clone())
     }
+}
 
-    pub fn frame_time(&self) -> u128 {
-        let frame_rate = self.decoder.frame_rate().unwrap();
-        (frame_rate.numerator() as f32 / frame_rate.denominator() as f32) as u128
+/// Send the packets of video stream `stream_index` of the live stream `ictx`,
+/// opened from `url`, to `packets`. The stream is reopened when it stops,
+/// until [`live::RECONNECTS`] attempts were made or the receiver is gone.
+/// This runs on its own thread so that waiting for the stream doesn't hold
+/// up drawing. While the receiver falls behind, as when paused, packets are
+/// skipped up to the next keyframe that fits rather than queued.
+fn read_live(url: &str, mut ictx: Input, mut stream_index: usize, packets: &SyncSender<Demuxed>) {
+    let mut reconnects = 0;
+    // Packets skipped since the last one sent, and whether to wait for a
+    // keyframe.
+    let mut dropped = 0;
+    let mut skipping = false;
+    loop {
+        for (stream, packet) in ictx.packets() {
+            if stream.index() != stream_index {
+                continue;
+            }
+            if skipping && !packet.is_key() {
+                dropped += 1;
+                continue;
+            }
+            match packets.try_send(Demuxed::Packet(packet)) {
+                Ok(()) => skipping = false,
+                Err(TrySendError::Full(_)) => {
+                    dropped += 1;
+                    skipping = true;
+                }
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+            if dropped > 0 && !skipping && packets.try_send(Demuxed::Dropped(dropped)).is_ok() {
+                dropped = 0;
+            }
+        }
+        // After reopening the stream starts over from a keyframe.
+        skipping = false;
+        // Failing to reopen counts as an attempt as well.
+        loop {
+            if reconnects == live::RECONNECTS || packets.send(Demuxed::Reconnecting).is_err() {
+                return;
+            }
+            reconnects += 1;
+            std::thread::sleep(live::RECONNECT_DELAY);
+            if let Ok(reopened) =
+                ffmpeg_next::format::input_with_dictionary(&url.to_string(), live::options(url))
+            {
+                if let Some(input) = reopened.streams().best(ffmpeg_next::media::Type::Video) {
+                    stream_index = input.index();
+                    let reopened = Demuxed::Reopened {
+                        time_base: input.time_base(),
+                        start_time: start_time(&input),
+                    };
+                    if packets.send(reopened).is_err() {
+                        return;
+                    }
+                }
+                ictx = reopened;
+                break;
+            }
+        }
+    }
+}
+
+/// Timestamp of the first frame of `stream`, or 0 if the container doesn't
+/// know it.
+pub(crate) fn start_time(stream: &ffmpeg_next::format::stream::Stream) -> i64 {
+    // AV_NOPTS_VALUE when unknown
+    Some(stream.start_time())
+        .filter(|&start_time| start_time != i64::MIN)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a clip of `frames` frames with B-frames into `path` with the
+    /// ffmpeg command, returning false if that fails.
+    fn encode_clip(path: &std::path::Path, frames: usize) -> bool {
+        let status = std::process::Command::new("ffmpeg")
+            .args(["-v", "error", "-y", "-f", "lavfi", "-i"])
+            .arg(format!(
+                "testsrc=size=64x48:rate=25,trim=end_frame={}",
+                frames
+            ))
+            .args(["-c:v", "mpeg4", "-bf", "2"])
+            .arg(path)
+            .status();
+        matches!(status, Ok(status) if status.success())
+    }
+
+    #[test]
+    #[ignore = "needs the ffmpeg command line tool to encode the clip"]
+    fn decodes_every_frame_of_clip_with_b_frames() {
+        let path = std::env::temp_dir().join(format!("b-frames-{}.mp4", std::process::id()));
+        assert!(encode_clip(&path, 30), "ffmpeg failed to encode the clip");
+        let mut video = VideoStream::new(path.to_str().unwrap());
+        let mut count = 0;
+        while video.get_next_frame().is_some() {
+            count += 1;
+        }
+        std::fs::remove_file(&path).ok();
+        assert_eq!(count, 30);
+        assert_eq!(video.frame_index(), 29);
     }
 }
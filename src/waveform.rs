@@ -0,0 +1,248 @@
+use std::sync::{Arc, Mutex};
+
+use crate::audio::AudioStream;
+use crate::loudness::{self, LoudnessMeter};
+use crate::overlay::{Color, Overlay};
+
+/// Rate the audio is decoded at for analysis.
+const SAMPLE_RATE: u32 = 48000;
+/// Waveform buckets per second.
+const BUCKETS: f64 = 100.0;
+/// Seconds of audio shown across a strip.
+const WINDOW: f64 = 4.0;
+/// Largest offset between inputs looked for and how much of the envelopes
+/// is correlated to find it, in seconds.
+const MAX_OFFSET: f64 = 10.0;
+const CORRELATED: f64 = 300.0;
+const STRIP_HEIGHT: f32 = 80.0;
+const TEXT_SCALE: f32 = 1.0;
+const BACKGROUND: Color = [0.0, 0.0, 0.0, 0.6];
+const WAVE: Color = [0.3, 0.85, 0.35, 0.9];
+const PLAYHEAD: Color = [1.0, 1.0, 1.0, 0.8];
+const TEXT: Color = [1.0, 1.0, 1.0, 1.0];
+
+#[derive(Default)]
+struct Analysis {
+    /// Lowest and highest sample of every bucket, mixed down to mono.
+    peaks: Vec<(f32, f32)>,
+    /// RMS of every bucket.
+    envelope: Vec<f32>,
+    loudness: Option<f64>,
+    true_peak: Option<f64>,
+    has_audio: bool,
+    done: bool,
+}
+
+/// Waveform, loudness and true peak of the audio of every input and their
+/// offsets against each other, analysed on background threads.
+pub struct Waveforms {
+    analyses: Vec<Arc<Mutex<Analysis>>>,
+    /// Offset of every input behind the first one in seconds, once all are
+    /// analysed.
+    offsets: Arc<Mutex<Option<Vec<Option<f64>>>>>,
+}
+
+impl Waveforms {
+    /// Analyse the audio of every input at `paths`. Inputs that can't be
+    /// read a second time, like stdin and live streams, show no audio.
+    pub fn spawn(paths: &[String]) -> Self {
+        let analyses = paths
+            .iter()
+            .map(|path| {
+                let analysis = Arc::new(Mutex::new(Analysis::default()));
+                let thread_analysis = analysis.clone();
+                let path = path.clone();
+                let thread = std::thread::spawn(move || analyse(&path, &thread_analysis));
+                (analysis, thread)
+            })
+            .collect::<Vec<_>>();
+        let offsets = Arc::new(Mutex::new(None));
+
+        let thread_offsets = offsets.clone();
+        let (analyses, threads): (Vec<_>, Vec<_>) = analyses.into_iter().unzip();
+        let thread_analyses = analyses.clone();
+        std::thread::spawn(move || {
+            for thread in threads {
+                // A failed analysis just shows no audio.
+                let _ = thread.join();
+            }
+            let envelopes = thread_analyses
+                .iter()
+                .map(|analysis| {
+                    let analysis = analysis.lock().unwrap();
+                    let length = analysis.envelope.len().min((CORRELATED * BUCKETS) as usize);
+                    analysis.envelope[..length].to_vec()
+                })
+                .collect::<Vec<_>>();
+            let found = envelopes
+                .iter()
+                .map(|envelope| loudness::offset(&envelopes[0], envelope, BUCKETS, MAX_OFFSET))
+                .collect();
+            *thread_offsets.lock().unwrap() = Some(found);
+        });
+
+        Self { analyses, offsets }
+    }
+
+    /// Add a strip for every input in a target of `size` ending at `bottom`,
+    /// each centred on `times`, the time in seconds of the frame shown of the
+    /// input. Offsets are given against input `reference`. Returns the top of
+    /// the strips for what goes above.
+    pub fn draw(
+        &self,
+        overlay: &mut Overlay,
+        size: (u32, u32),
+        bottom: f32,
+        times: &[f64],
+        reference: usize,
+    ) -> f32 {
+        let count = self.analyses.len();
+        let top = bottom - STRIP_HEIGHT;
+        let width = size.0 as f32 / count as f32;
+        let offsets = self.offsets.lock().unwrap();
+
+        for (index, (analysis, &time)) in self.analyses.iter().zip(times).enumerate() {
+            let analysis = analysis.lock().unwrap();
+            let left = index as f32 * width;
+            overlay.rect(left + 1.0, top, width - 2.0, STRIP_HEIGHT, BACKGROUND);
+
+            let first = (time - WINDOW / 2.0) * BUCKETS;
+            let per_pixel = WINDOW * BUCKETS / width as f64;
+            let middle = top + STRIP_HEIGHT / 2.0;
+            for x in 0..width as usize {
+                let start = first + x as f64 * per_pixel;
+                let end = start + per_pixel.max(1.0);
+                if start < 0.0 {
+                    continue;
+                }
+                let buckets = match analysis
+                    .peaks
+                    .get(start as usize..(end as usize).min(analysis.peaks.len()))
+                {
+                    Some(buckets) if !buckets.is_empty() => buckets,
+                    _ => continue,
+                };
+                let (low, high) = buckets
+                    .iter()
+                    .fold((0f32, 0f32), |(low, high), &(min, max)| {
+                        (low.min(min), high.max(max))
+                    });
+                let half = STRIP_HEIGHT / 2.0;
+                overlay.rect(
+                    left + x as f32,
+                    middle - high.min(1.0) * half,
+                    1.0,
+                    ((high - low).min(2.0) * half).max(1.0),
+                    WAVE,
+                );
+            }
+            overlay.rect(left + width / 2.0, top, 1.0, STRIP_HEIGHT, PLAYHEAD);
+
+            let offset = match offsets.as_ref() {
+                Some(offsets) if index != reference => {
+                    match (offsets[index], offsets.get(reference).copied().flatten()) {
+                        (Some(offset), Some(reference)) => {
+                            format!("  offset {:+.0} ms", (offset - reference) * 1000.0)
+                        }
+                        _ => "  offset unknown".to_string(),
+                    }
+                }
+                _ => String::new(),
+            };
+            let text = if analysis.done && !analysis.has_audio {
+                "no audio".to_string()
+            } else {
+                format!(
+                    "{}  {}{}{}",
+                    match analysis.loudness {
+                        Some(loudness) => format!("{:.1} LUFS", loudness),
+                        None => "-inf LUFS".to_string(),
+                    },
+                    match analysis.true_peak {
+                        Some(peak) => format!("TP {:.1} dBTP", peak),
+                        None => "TP -inf dBTP".to_string(),
+                    },
+                    offset,
+                    if analysis.done { "" } else { "  analysing" },
+                )
+            };
+            overlay.text(left + 4.0, top + 4.0, TEXT_SCALE, &text, TEXT);
+        }
+        top
+    }
+}
+
+/// Decode all of the audio at `path` into `analysis`, updating the loudness
+/// as it goes. Buckets are placed by the timestamps of the audio, from the
+/// first video frame on, so that strips line up with the video and gaps stay
+/// silent.
+fn analyse(path: &str, analysis: &Mutex<Analysis>) {
+    let mut stream = match AudioStream::open_native(path, SAMPLE_RATE) {
+        Some(stream) => stream,
+        None => {
+            analysis.lock().unwrap().done = true;
+            return;
+        }
+    };
+    let channels = stream.channels().max(1) as usize;
+    let bucket_length = (SAMPLE_RATE as f64 / BUCKETS) as usize * channels;
+    let mut meter = LoudnessMeter::new(SAMPLE_RATE, channels as u16);
+    analysis.lock().unwrap().has_audio = true;
+
+    // Samples of the bucket being filled.
+    let mut pending = Vec::with_capacity(bucket_length);
+    // Time of the next sample in samples per channel from the first video
+    // frame.
+    let mut next = 0i64;
+    while let Some((start, samples)) = stream.next_samples() {
+        meter.add(&samples);
+        let at = (start * SAMPLE_RATE as f64).round() as i64;
+        let frames = (samples.len() / channels) as i64;
+        // Silence up to the samples, and nothing of what overlaps or comes
+        // before the first video frame.
+        let silence = vec![0.0; (at - next).max(0) as usize * channels];
+        let skip = ((next - at).max(0) as usize * channels).min(samples.len());
+        next = next.max(at + frames);
+
+        let mut buckets = Vec::new();
+        for sample in silence.into_iter().chain(samples[skip..].iter().copied()) {
+            pending.push(sample);
+            if pending.len() == bucket_length {
+                buckets.push(bucket(&pending, channels));
+                pending.clear();
+            }
+        }
+
+        let mut analysis = analysis.lock().unwrap();
+        let before = analysis.peaks.len();
+        for (peaks, rms) in buckets {
+            analysis.peaks.push(peaks);
+            analysis.envelope.push(rms);
+        }
+        // Measuring goes over everything so far, once a second is enough.
+        if before / BUCKETS as usize != analysis.peaks.len() / BUCKETS as usize {
+            update(&mut analysis, &meter);
+        }
+    }
+    let mut analysis = analysis.lock().unwrap();
+    update(&mut analysis, &meter);
+    analysis.done = true;
+}
+
+fn update(analysis: &mut Analysis, meter: &LoudnessMeter) {
+    analysis.loudness = meter.integrated();
+    analysis.true_peak = Some(meter.true_peak()).filter(|peak| peak.is_finite());
+}
+
+/// Peaks of the mono mix of interleaved `samples` and their RMS.
+fn bucket(samples: &[f32], channels: usize) -> ((f32, f32), f32) {
+    let mut peaks = (0f32, 0f32);
+    let mut sum = 0.0;
+    for frame in samples.chunks_exact(channels) {
+        let mono = frame.iter().sum::<f32>() / channels as f32;
+        peaks = (peaks.0.min(mono), peaks.1.max(mono));
+        sum += mono * mono;
+    }
+    let rms = (sum / (samples.len() / channels).max(1) as f32).sqrt();
+    (peaks, rms)
+}
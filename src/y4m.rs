@@ -0,0 +1,442 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+use ffmpeg_next::{color, format::Pixel, util::frame::Video, Rational};
+
+use crate::raw;
+use crate::source::FrameSource;
+
+/// Colour space tags and the pixel formats they stand for. The first tag of a
+/// format is the one written.
+const COLOUR_SPACES: &[(&str, Pixel)] = &[
+    ("420jpeg", Pixel::YUV420P),
+    ("420paldv", Pixel::YUV420P),
+    ("420mpeg2", Pixel::YUV420P),
+    ("420", Pixel::YUV420P),
+    ("411", Pixel::YUV411P),
+    ("422", Pixel::YUV422P),
+    ("444", Pixel::YUV444P),
+    ("444alpha", Pixel::YUVA444P),
+    ("mono", Pixel::GRAY8),
+    ("mono16", Pixel::GRAY16LE),
+    ("420p10", Pixel::YUV420P10LE),
+    ("422p10", Pixel::YUV422P10LE),
+    ("444p10", Pixel::YUV444P10LE),
+    ("420p12", Pixel::YUV420P12LE),
+    ("422p12", Pixel::YUV422P12LE),
+    ("444p12", Pixel::YUV444P12LE),
+    ("420p16", Pixel::YUV420P16LE),
+    ("422p16", Pixel::YUV422P16LE),
+    ("444p16", Pixel::YUV444P16LE),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interlacing {
+    Progressive,
+    TopFieldFirst,
+    BottomFieldFirst,
+    /// Given per frame.
+    Mixed,
+}
+
+/// The stream header of a YUV4MPEG2 file.
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub dimensions: (u32, u32),
+    pub frame_rate: Rational,
+    pub pixel_aspect: Rational,
+    pub format: Pixel,
+    pub interlacing: Interlacing,
+    pub full_range: bool,
+}
+
+impl Header {
+    /// Progressive video in limited range with square pixels.
+    pub fn new(dimensions: (u32, u32), frame_rate: Rational, format: Pixel) -> Self {
+        Self {
+            dimensions,
+            frame_rate,
+            pixel_aspect: Rational::new(1, 1),
+            format,
+            interlacing: Interlacing::Progressive,
+            full_range: false,
+        }
+    }
+
+    /// Parse the first line of a file, without the newline.
+    pub fn parse(line: &str) -> Self {
+        let mut parameters = line.split(' ');
+        assert_eq!(parameters.next(), Some("YUV4MPEG2"), "not a Y4M stream");
+
+        let mut header = Self::new((0, 0), (25, 1).into(), Pixel::YUV420P);
+        for parameter in parameters.filter(|parameter| !parameter.is_empty()) {
+            let tag = parameter.chars().next().unwrap();
+            let value = &parameter[tag.len_utf8()..];
+            match tag {
+                'W' => header.dimensions.0 = value.parse().expect("Y4M width"),
+                'H' => header.dimensions.1 = value.parse().expect("Y4M height"),
+                'F' => header.frame_rate = parse_ratio(value).expect("Y4M frame rate"),
+                'A' => {
+                    // 0:0 means unknown.
+                    if let Some(aspect) = parse_ratio(value) {
+                        header.pixel_aspect = aspect;
+                    }
+                }
+                'C' => {
+                    header.format = COLOUR_SPACES
+                        .iter()
+                        .find(|(name, _)| *name == value)
+                        .map(|&(_, format)| format)
+                        .unwrap_or_else(|| panic!("unsupported Y4M colour space {}", value))
+                }
+                'I' => {
+                    header.interlacing = match value {
+                        "t" => Interlacing::TopFieldFirst,
+                        "b" => Interlacing::BottomFieldFirst,
+                        "m" => Interlacing::Mixed,
+                        _ => Interlacing::Progressive,
+                    }
+                }
+                'X' if value == "COLORRANGE=FULL" => header.full_range = true,
+                _ => {}
+            }
+        }
+        assert!(
+            header.dimensions.0 > 0 && header.dimensions.1 > 0,
+            "Y4M stream without a size"
+        );
+        header
+    }
+
+    fn line(&self) -> String {
+        let colour_space = COLOUR_SPACES
+            .iter()
+            .find(|&&(_, format)| format == self.format)
+            .map(|(name, _)| name)
+            .unwrap_or_else(|| panic!("Y4M can't store {:?}", self.format));
+        let interlacing = match self.interlacing {
+            Interlacing::Progressive => "p",
+            Interlacing::TopFieldFirst => "t",
+            Interlacing::BottomFieldFirst => "b",
+            Interlacing::Mixed => "m",
+        };
+        format!(
+            "YUV4MPEG2 W{} H{} F{}:{} I{} A{}:{} C{} XCOLORRANGE={}\n",
+            self.dimensions.0,
+            self.dimensions.1,
+            self.frame_rate.numerator(),
+            self.frame_rate.denominator(),
+            interlacing,
+            self.pixel_aspect.numerator(),
+            self.pixel_aspect.denominator(),
+            colour_space,
+            if self.full_range { "FULL" } else { "LIMITED" },
+        )
+    }
+}
+
+fn parse_ratio(ratio: &str) -> Option<Rational> {
+    let (numerator, denominator) = ratio.split_once(':')?;
+    let (numerator, denominator) = (numerator.parse().ok()?, denominator.parse().ok()?);
+    if numerator <= 0 || denominator <= 0 {
+        return None;
+    }
+    Some(Rational::new(numerator, denominator))
+}
+
+/// A YUV4MPEG2 file, which seeks exactly to any frame already read and by
+/// reading ahead to later ones. YUV4MPEG2 piped to stdin is read by ffmpeg.
+pub struct Y4mVideo {
+    input: BufReader<File>,
+    header: Header,
+    row_sizes: Vec<usize>,
+    frame_size: usize,
+    /// Where every frame read so far starts, and where the next one does.
+    offsets: Vec<u64>,
+    /// Index of the frame the input is positioned at.
+    position: usize,
+    frame_index: Option<usize>,
+}
+
+impl Y4mVideo {
+    pub fn open(path: &str) -> Self {
+        let mut input = BufReader::new(File::open(path).expect("to read the input"));
+        let mut line = Vec::new();
+        input
+            .read_until(b'\n', &mut line)
+            .expect("read the Y4M header");
+        let header = Header::parse(String::from_utf8_lossy(&line).trim_end());
+        if header.interlacing != Interlacing::Progressive {
+            eprintln!(
+                "{} is interlaced, its fields are shown woven together",
+                path
+            );
+        }
+        let row_sizes = raw::row_sizes(header.format, header.dimensions);
+        let frame_size = raw::frame_size(header.format, header.dimensions, &row_sizes);
+
+        Self {
+            input,
+            header,
+            row_sizes,
+            frame_size,
+            offsets: vec![line.len() as u64],
+            position: 0,
+            frame_index: None,
+        }
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn read_frame(&mut self) -> Option<Video> {
+        let mut line = Vec::new();
+        self.input
+            .read_until(b'\n', &mut line)
+            .expect("read a Y4M frame header");
+        if !line.starts_with(b"FRAME") {
+            return None;
+        }
+        let mut data = vec![0; self.frame_size];
+        self.input.read_exact(&mut data).ok()?;
+
+        let index = self.position;
+        self.position += 1;
+        if self.offsets.len() == self.position {
+            let offset = self.offsets[index] + (line.len() + self.frame_size) as u64;
+            self.offsets.push(offset);
+        }
+
+        let mut frame = raw::unpack(
+            self.header.format,
+            self.header.dimensions,
+            &self.row_sizes,
+            &data,
+        );
+        if self.header.full_range {
+            frame.set_color_range(color::Range::JPEG);
+        }
+        frame.set_pts(Some(index as i64));
+        self.frame_index = Some(index);
+        Some(frame)
+    }
+}
+
+impl FrameSource for Y4mVideo {
+    fn next_frame(&mut self) -> Option<Video> {
+        self.read_frame()
+    }
+
+    fn seek(&mut self, index: usize) -> Option<Video> {
+        if index < self.position {
+            self.input
+                .seek(SeekFrom::Start(self.offsets[index]))
+                .expect("seek in the input");
+            self.position = index;
+        }
+        while self.position < index {
+            self.read_frame()?;
+        }
+        self.read_frame()
+    }
+
+    fn frame_index(&self) -> usize {
+        self.frame_index.unwrap_or(0)
+    }
+
+    fn frame_rate(&self) -> Rational {
+        self.header.frame_rate
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.header.dimensions
+    }
+
+    fn format(&self) -> Pixel {
+        self.header.format
+    }
+}
+
+/// Writes frames as a YUV4MPEG2 stream, to stdout for `-`.
+pub struct Y4mWriter {
+    writer: Box<dyn Write>,
+    row_sizes: Vec<usize>,
+}
+
+impl Y4mWriter {
+    pub fn create(path: &str, header: &Header) -> Self {
+        let mut writer: Box<dyn Write> = if path == "-" {
+            Box::new(std::io::BufWriter::new(std::io::stdout()))
+        } else {
+            Box::new(std::io::BufWriter::new(
+                File::create(path).expect("to create the output"),
+            ))
+        };
+        writer
+            .write_all(header.line().as_bytes())
+            .expect("write the Y4M header");
+
+        Self {
+            writer,
+            row_sizes: raw::row_sizes(header.format, header.dimensions),
+        }
+    }
+
+    /// Write `frame`, which has to have the format and size of the header.
+    pub fn write(&mut self, frame: &Video) {
+        self.writer.write_all(b"FRAME\n").expect("write a frame");
+        raw::pack(frame, &self.row_sizes, &mut self.writer);
+    }
+
+    pub fn finish(mut self) {
+        self.writer.flush().expect("flush the output");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_header() {
+        let header = Header::parse(
+            "YUV4MPEG2 W1920 H1080 F30000:1001 It A128:117 C422p10 XYSCSS=422P10 XCOLORRANGE=FULL",
+        );
+        assert_eq!(header.dimensions, (1920, 1080));
+        assert_eq!(header.frame_rate, Rational::new(30000, 1001));
+        assert_eq!(header.pixel_aspect, Rational::new(128, 117));
+        assert_eq!(header.format, Pixel::YUV422P10LE);
+        assert_eq!(header.interlacing, Interlacing::TopFieldFirst);
+        assert!(header.full_range);
+    }
+
+    #[test]
+    fn defaults_of_missing_parameters() {
+        let header = Header::parse("YUV4MPEG2 W64 H48  A0:0");
+        assert_eq!(header.frame_rate, Rational::new(25, 1));
+        assert_eq!(header.pixel_aspect, Rational::new(1, 1));
+        assert_eq!(header.format, Pixel::YUV420P);
+        assert_eq!(header.interlacing, Interlacing::Progressive);
+        assert!(!header.full_range);
+    }
+
+    #[test]
+    fn skips_unknown_parameters() {
+        let header = Header::parse("YUV4MPEG2 W64 ÄH H48 é Xanything");
+        assert_eq!(header.dimensions, (64, 48));
+    }
+
+    #[test]
+    fn writes_the_header_it_parses() {
+        let mut header = Header::new((352, 288), Rational::new(50, 1), Pixel::YUV444P);
+        header.interlacing = Interlacing::BottomFieldFirst;
+        header.full_range = true;
+        let line = header.line();
+        assert!(line.ends_with('\n'));
+        let parsed = Header::parse(line.trim_end());
+        assert_eq!(parsed.dimensions, header.dimensions);
+        assert_eq!(parsed.frame_rate, header.frame_rate);
+        assert_eq!(parsed.format, header.format);
+        assert_eq!(parsed.interlacing, header.interlacing);
+        assert!(parsed.full_range);
+    }
+
+    #[test]
+    #[should_panic(expected = "not a Y4M stream")]
+    fn rejects_other_signatures() {
+        Header::parse("YUV4MPEG W64 H48");
+    }
+
+    #[test]
+    #[should_panic(expected = "without a size")]
+    fn rejects_missing_size() {
+        Header::parse("YUV4MPEG2 W64");
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported Y4M colour space")]
+    fn rejects_unknown_colour_space() {
+        Header::parse("YUV4MPEG2 W64 H48 C420p9");
+    }
+
+    /// 4x2 yuv420p frame with every plane filled with its own value.
+    fn frame(index: u8) -> Video {
+        let mut frame = Video::new(Pixel::YUV420P, 4, 2);
+        for plane in 0..3 {
+            frame.data_mut(plane).fill(10 * (plane as u8 + 1) + index);
+        }
+        frame
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let path = std::env::temp_dir().join(format!("round-trip-{}.y4m", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut header = Header::new((4, 2), Rational::new(30, 1), Pixel::YUV420P);
+        header.full_range = true;
+        let mut writer = Y4mWriter::create(path, &header);
+        for index in 0..3 {
+            writer.write(&frame(index));
+        }
+        writer.finish();
+
+        let written = std::fs::read(path).unwrap();
+        let line = String::from_utf8_lossy(&written);
+        let line = line.lines().next().unwrap();
+        assert!(line.contains(" C420jpeg "), "{}", line);
+        assert!(line.ends_with(" XCOLORRANGE=FULL"), "{}", line);
+
+        let mut video = Y4mVideo::open(path);
+        assert_eq!(video.dimensions(), (4, 2));
+        assert_eq!(video.frame_rate(), Rational::new(30, 1));
+        assert_eq!(video.format(), Pixel::YUV420P);
+        assert!(video.header().full_range);
+        let same = |read: &Video, index: u8| {
+            let written = frame(index);
+            (0..3).all(|plane| {
+                let (width, height) = if plane == 0 { (4, 2) } else { (2, 1) };
+                (0..height).all(|row| {
+                    let read_row = row * read.stride(plane);
+                    let written_row = row * written.stride(plane);
+                    read.data(plane)[read_row..read_row + width]
+                        == written.data(plane)[written_row..written_row + width]
+                })
+            })
+        };
+
+        for index in 0..3 {
+            let read = video.next_frame().unwrap();
+            assert!(same(&read, index), "frame {}", index);
+            assert_eq!(read.color_range(), color::Range::JPEG);
+            assert_eq!(video.frame_index(), index as usize);
+        }
+        assert!(video.next_frame().is_none());
+
+        // Back to frames already read and on to the last again.
+        assert!(same(&video.seek(0).unwrap(), 0));
+        assert!(same(&video.seek(2).unwrap(), 2));
+        assert!(same(&video.seek(1).unwrap(), 1));
+        assert_eq!(video.frame_index(), 1);
+        assert!(video.seek(3).is_none());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn seeks_ahead_of_the_frames_read() {
+        let path = std::env::temp_dir().join(format!("seek-ahead-{}.y4m", std::process::id()));
+        let path = path.to_str().unwrap();
+        let header = Header::new((4, 2), Rational::new(25, 1), Pixel::YUV420P);
+        let mut writer = Y4mWriter::create(path, &header);
+        for index in 0..4 {
+            writer.write(&frame(index));
+        }
+        writer.finish();
+
+        let mut video = Y4mVideo::open(path);
+        assert_eq!(video.seek(3).unwrap().data(0)[0], 13);
+        assert_eq!(video.seek(1).unwrap().data(1)[0], 21);
+        assert_eq!(video.next_frame().unwrap().data(2)[0], 32);
+        std::fs::remove_file(path).ok();
+    }
+}